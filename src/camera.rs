@@ -1,4 +1,32 @@
-use crate::Vec3;
+use crate::{
+    vec3::{cross, unit_vector},
+    Vec3,
+};
+
+/// Where the camera is placed and what it looks at
+#[derive(Copy, Clone, Debug)]
+pub struct CameraSettings {
+    /// Position of the camera
+    pub position: Vec3,
+    /// Point the camera looks towards
+    pub look_at: Vec3,
+    /// Which direction is up for the camera
+    pub up: Vec3,
+    /// Vertical field of view in degrees
+    pub vertical_fov: f64,
+}
+
+impl Default for CameraSettings {
+    /// Camera at the origin looking down -z, with a viewport 2 units high at a distance of 5
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            look_at: Vec3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            vertical_fov: 2.0 * (1.0_f64 / 5.0).atan().to_degrees(),
+        }
+    }
+}
 
 pub(crate) struct Camera {
     pub(crate) origin: Vec3,
@@ -8,18 +36,25 @@ pub(crate) struct Camera {
 }
 
 impl Camera {
-    pub(crate) fn with_aspect_ratio(viewport_aspect_ratio: f64) -> Self {
+    pub(crate) fn new(settings: CameraSettings, viewport_aspect_ratio: f64) -> Self {
+        // Rays aren't normalized, so keep the viewport at the same distance for any field of view
+        // This keeps the background gradient, which uses the ray direction, the same
+        let focal_length = 5.0;
+
         // Viewport properties
-        let viewport_height = 2.0;
+        let viewport_height = 2.0 * (settings.vertical_fov.to_radians() / 2.0).tan() * focal_length;
         let viewport_width = viewport_aspect_ratio * viewport_height;
 
+        // Orthonormal basis, the camera looks down -w
+        let w = unit_vector(settings.position - settings.look_at);
+        let u = unit_vector(cross(settings.up, w));
+        let v = cross(w, u);
+
         // Camera properties
-        let focal_length = 5.0;
-        let origin = Vec3::new(0.0, 0.0, 0.0);
-        let horizontal = Vec3::new(viewport_width, 0.0, 0.0);
-        let vertical = Vec3::new(0.0, viewport_height, 0.0);
-        let lower_left_corner =
-            origin - (horizontal / 2.0) - (vertical / 2.0) - Vec3::new(0.0, 0.0, focal_length);
+        let origin = settings.position;
+        let horizontal = u * viewport_width;
+        let vertical = v * viewport_height;
+        let lower_left_corner = origin - (horizontal / 2.0) - (vertical / 2.0) - w * focal_length;
 
         Self {
             origin,
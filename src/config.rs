@@ -1,14 +1,251 @@
-use crate::RayTracer;
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    integrator::{AoIntegrator, Integrator},
+    progress::{CancelToken, Progress, ProgressCallback},
+    sampler::SamplerKind,
+    CameraSettings, RayTracer, Vec3,
+};
 
 /// Determine which drawing mode to use
-/// * 'Colors' - Draw only the colors of the objects
-/// * 'Normals' - Draw only the normals of the objects
-/// * 'Samples' - Draw the final image with sampling
-#[derive(Copy, Clone)]
+/// * 'Colors' - Draw only the colors of the objects, with one sample in the corner of each pixel
+/// * 'Normals' - Draw only the normals of the objects, with one sample in the corner of each pixel
+/// * 'Samples' - Draw the final image with sampling, taking this many samples per pixel or at least this many with adaptive sampling
+/// * 'AmbientOcclusion' - Draw how open the surroundings of each point are in gray, sampled like 'Samples'
+/// * 'Depth' - Draw the distance from the camera in gray, black at near and white at far, None fits them to the scene
+/// * 'Position' - Draw the world position of the objects, across the box around the scene
+/// * 'Uv' - Draw the texture coordinates of the objects
+/// * 'Barycentric' - Draw the position within each triangle
+/// * 'FaceOrientation' - Draw the front of the triangles blue and the back red
+/// * 'Ids' - Draw the object or material IDs in false colors, each ID with its own color
+/// * The debug modes after 'AmbientOcclusion' take one sample in the corner of each pixel, like 'Colors'
+#[derive(Copy, Clone, Debug)]
 pub enum DrawingMode {
     Colors,
     Normals,
     Samples(u32),
+    AmbientOcclusion(u32),
+    Depth { near: Option<f64>, far: Option<f64> },
+    Position,
+    Uv,
+    Barycentric,
+    FaceOrientation,
+    Ids(IdKind),
+}
+
+impl DrawingMode {
+    /// Samples per pixel of the modes which jitter their samples within the pixels
+    /// # Returns
+    /// * None for the modes with one sample in the corner of each pixel
+    pub(crate) fn samples(&self) -> Option<u32> {
+        match *self {
+            DrawingMode::Samples(samples) | DrawingMode::AmbientOcclusion(samples) => Some(samples),
+            DrawingMode::Colors
+            | DrawingMode::Normals
+            | DrawingMode::Depth { .. }
+            | DrawingMode::Position
+            | DrawingMode::Uv
+            | DrawingMode::Barycentric
+            | DrawingMode::FaceOrientation
+            | DrawingMode::Ids(_) => None,
+        }
+    }
+}
+
+/// Which ID the world gave what a ray hits
+/// * 'Object' - ID of the mesh or instance, in the order they were added to the world
+/// * 'Material' - ID of the material, instances of the same mesh share them
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IdKind {
+    Object,
+    Material,
+}
+
+/// What rays which don't hit anything see
+/// * 'Gradient' - Blend from the bottom color to the top color based on the ray's direction
+/// * 'Color' - A single color in every direction
+#[derive(Copy, Clone, Debug)]
+pub enum Background {
+    Gradient { top: Vec3, bottom: Vec3 },
+    Color(Vec3),
+}
+
+impl Default for Background {
+    /// The blueish sky gradient
+    fn default() -> Self {
+        Background::Gradient {
+            top: Vec3::new(0.5, 0.7, 1.0),
+            bottom: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Background {
+    /// Calculate the color seen in a direction
+    /// # Arguments
+    /// * 'direction' - Direction of the ray which didn't hit anything
+    pub(crate) fn color(&self, direction: Vec3) -> Vec3 {
+        match *self {
+            Background::Gradient { top, bottom } => {
+                let t = (direction.y + 1.0) * 0.5;
+
+                // Typical interpolation
+                (bottom * (1.0 - t)) + top * t
+            }
+            Background::Color(color) => color,
+        }
+    }
+}
+
+/// Order tiles are handed to the rendering threads in
+/// * 'Scanline' - Row by row from the top left, finished rows can be written out early
+/// * 'Spiral' - Outwards from the center of the image, which usually holds the subject
+/// * 'Hilbert' - Along a Hilbert curve, keeping tiles rendered at the same time close together
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    #[default]
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+/// Take more samples only in pixels which are still noisy
+/// # Notes
+/// * Each pixel starts with the samples of DrawingMode::Samples, then takes batches of as many samples again until it's converged
+/// * The stratified sampler spreads each batch over its own cells, so every batch is evenly spread however many are taken
+/// * The noise is the standard error of the pixel's luminance after gamma correction, so it matches how visible the noise is
+#[derive(Copy, Clone, Debug)]
+pub struct AdaptiveSampling {
+    /// Most samples taken for one pixel
+    pub max_samples: u32,
+    /// Noise at which a pixel counts as converged, 0.01 is about 2.5 of the 255 levels of the output
+    pub noise_threshold: f64,
+}
+
+impl Default for AdaptiveSampling {
+    /// Up to 256 samples per pixel, until the noise is below 0.01
+    fn default() -> Self {
+        Self {
+            max_samples: 256,
+            noise_threshold: 0.01,
+        }
+    }
+}
+
+/// Shapes of reconstruction filters
+/// * 'Box' - Every sample within the radius counts the same, with a radius of 0.5 samples only count for their own pixel
+/// * 'Tent' - Weights fall linearly to 0 at the radius
+/// * 'Gaussian' - Gaussian with a standard deviation of a third of the radius, shifted down to reach 0 at the radius
+/// * 'Mitchell' - Mitchell–Netravali cubic with B = C = 1/3, sharper than the Gaussian with a little ringing
+/// * 'Lanczos' - Sinc windowed by a wider sinc, the sharpest with the most ringing
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+/// Reconstruction filter which weights the samples around each pixel
+/// # Notes
+/// * Samples count for every pixel whose center is within the radius horizontally and vertically
+/// * The weights in x and y are multiplied, so the filter covers a square
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    /// Distance from the pixel center in pixels at which the weights reach 0
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    /// A box filter with a radius of 0.5, each pixel averages its own samples
+    fn default() -> Self {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+impl Filter {
+    /// Create a filter with the usual radius of its kind
+    /// # Arguments
+    /// * 'kind' - Shape of the filter, the radius is 0.5 for Box, 1 for Tent, 1.5 for Gaussian, 2 for Mitchell and 3 for Lanczos
+    pub fn new(kind: FilterKind) -> Self {
+        let radius = match kind {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        };
+        Self { kind, radius }
+    }
+
+    /// Weight of a sample
+    /// # Arguments
+    /// * 'dx, dy' - Offset of the sample from the pixel center, in pixels
+    pub(crate) fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    /// Number of pixels next to a pixel which its samples can count for, in each direction
+    pub(crate) fn margin(&self) -> u32 {
+        // Samples are up to half a pixel from the center of their own pixel
+        ((self.radius + 0.5).ceil() as u32).saturating_sub(1)
+    }
+
+    fn weight_1d(&self, d: f64) -> f64 {
+        let radius = self.radius;
+
+        // Including -radius but not radius, so a box with a radius of 0.5 takes each sample exactly once
+        if d < -radius || d >= radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - d.abs() / radius,
+            FilterKind::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(d) - gaussian(radius)).max(0.0)
+            }
+            FilterKind::Mitchell => {
+                // The cubic is defined from -2 to 2
+                let x = (2.0 * d / radius).abs();
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            FilterKind::Lanczos => sinc(d) * sinc(d / radius),
+        }
+    }
+}
+
+/// The normalized sinc function, sin(pi x) / (pi x)
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-8 {
+        return 1.0;
+    }
+    let x = PI * x;
+    x.sin() / x
+}
+
+/// A rectangle of the image, in pixels from the top left corner
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
 }
 
 pub struct RayTracerConfig {
@@ -16,6 +253,20 @@ pub struct RayTracerConfig {
     pub(crate) width: u32,
     pub(crate) height: u32,
     pub(crate) max_depth: u32,
+    pub(crate) roulette_depth: u32,
+    pub(crate) integrator: Option<Arc<dyn Integrator>>,
+    pub(crate) ambient_occlusion: AoIntegrator,
+    pub(crate) camera: CameraSettings,
+    pub(crate) background: Background,
+    pub(crate) seed: u64,
+    pub(crate) sampler: SamplerKind,
+    pub(crate) adaptive: Option<AdaptiveSampling>,
+    pub(crate) filter: Filter,
+    pub(crate) region: Option<Region>,
+    pub(crate) tile_size: u32,
+    pub(crate) tile_order: TileOrder,
+    pub(crate) on_progress: Option<ProgressCallback>,
+    pub(crate) cancel: CancelToken,
 }
 
 impl Default for RayTracerConfig {
@@ -24,7 +275,21 @@ impl Default for RayTracerConfig {
             mode: DrawingMode::Samples(3),
             width: 480,
             height: 270,
-            max_depth: 5,
+            max_depth: 32,
+            roulette_depth: 3,
+            integrator: None,
+            ambient_occlusion: AoIntegrator::default(),
+            camera: CameraSettings::default(),
+            background: Background::default(),
+            seed: 0,
+            sampler: SamplerKind::default(),
+            adaptive: None,
+            filter: Filter::default(),
+            region: None,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            on_progress: None,
+            cancel: CancelToken::new(),
         }
     }
 }
@@ -39,6 +304,11 @@ impl RayTracerConfig {
         self
     }
 
+    /// The drawing mode set so far, e.g. by a scene file
+    pub fn drawing_mode(&self) -> DrawingMode {
+        self.mode
+    }
+
     pub fn width(mut self, width: u32) -> Self {
         self.width = width;
         self
@@ -49,12 +319,182 @@ impl RayTracerConfig {
         self
     }
 
+    /// Most bounces a path can have
+    /// # Arguments
+    /// * 'max_depth' - Hard limit on the length of paths, 32 by default, Russian roulette usually stops them long before
     pub fn max_depth(mut self, max_depth: u32) -> Self {
         self.max_depth = max_depth;
         self
     }
 
+    /// Bounces after which paths may be stopped by Russian roulette
+    /// # Arguments
+    /// * 'roulette_depth' - Bounces every path gets, 3 by default, higher values are less noisy but slower
+    pub fn roulette_depth(mut self, roulette_depth: u32) -> Self {
+        self.roulette_depth = roulette_depth;
+        self
+    }
+
+    /// Calculate the color of the samples with a custom integrator
+    /// # Arguments
+    /// * 'integrator' - Replaces the integrator of the drawing mode, the mode still decides how many samples each pixel gets
+    pub fn integrator(mut self, integrator: impl Integrator + 'static) -> Self {
+        self.integrator = Some(Arc::new(integrator));
+        self
+    }
+
+    /// Set how ambient occlusion is sampled
+    /// # Arguments
+    /// * 'ambient_occlusion' - Rays and their distance, used by DrawingMode::AmbientOcclusion
+    pub fn ambient_occlusion(mut self, ambient_occlusion: AoIntegrator) -> Self {
+        self.ambient_occlusion = ambient_occlusion;
+        self
+    }
+
+    /// The ambient occlusion settings set so far, e.g. by a scene file
+    pub fn ambient_occlusion_settings(&self) -> AoIntegrator {
+        self.ambient_occlusion
+    }
+
+    pub fn camera(mut self, camera: CameraSettings) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    pub fn background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Seed all random sampling, renders with the same seed and settings are identical
+    /// # Notes
+    /// * Every sample of every pixel gets its own generator derived from the seed, so the thread count and tile order don't matter
+    /// * The seed is 0 by default, change it to get a different pattern of noise
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Pick how the random values of the samples are generated
+    /// # Arguments
+    /// * 'sampler' - Kind of sampler, the independent one by default
+    pub fn sampler(mut self, sampler: SamplerKind) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Spend more samples on noisy pixels than on converged ones
+    /// # Arguments
+    /// * 'adaptive' - How many samples may be taken and when a pixel is converged, only used by DrawingMode::Samples and AmbientOcclusion
+    pub fn adaptive(mut self, adaptive: AdaptiveSampling) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Pick the reconstruction filter which weights the samples around each pixel
+    /// # Arguments
+    /// * 'filter' - Filter used by DrawingMode::Samples and AmbientOcclusion, its radius is at least 0.5 so each sample counts for its own pixel
+    /// # Notes
+    /// * Filters wider than 0.5 trace a margin of pixels around each tile as well, with 32 pixel tiles that's
+    ///   about 13% more pixels for Tent and Gaussian, 27% for Mitchell and 41% for Lanczos, larger tiles lower the cost
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Filter {
+            radius: filter.radius.max(0.5),
+            ..filter
+        };
+        self
+    }
+
+    /// Only render part of the image, the camera still frames the whole image
+    /// # Arguments
+    /// * 'region' - Part of the image to render, clipped to the image
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Size of the square tiles the image is rendered in
+    /// # Arguments
+    /// * 'tile_size' - Width and height of the tiles in pixels, at least 1
+    pub fn tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+
+    /// Order the tiles are rendered in
+    pub fn tile_order(mut self, tile_order: TileOrder) -> Self {
+        self.tile_order = tile_order;
+        self
+    }
+
+    /// Report the progress of renders
+    /// # Arguments
+    /// * 'callback' - Called each time a tile is finished, from the rendering threads
+    pub fn on_progress(mut self, callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Allow renders to be stopped from another thread
+    /// # Arguments
+    /// * 'token' - Clone of a token which is kept to cancel the render
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
     pub fn build(self) -> RayTracer {
         RayTracer::new(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Offsets from a sample to the centers of its own pixel and the pixels next to it
+    fn offsets(u: f64, margin: i32) -> impl Iterator<Item = f64> {
+        (-margin - 1..=margin + 1).map(move |k| u - (k as f64 + 0.5))
+    }
+
+    #[test]
+    fn box_weights_sum_to_one() {
+        let filter = Filter::default();
+        assert_eq!(filter.margin(), 0);
+        for i in 0..=16 {
+            // Samples anywhere in the pixel, including on its left and top edges
+            let u = i as f64 / 16.0 * 0.999_999;
+            let sum: f64 = offsets(u, 1)
+                .flat_map(|dx| offsets(u, 1).map(move |dy| filter.weight(dx, dy)))
+                .sum();
+            assert_eq!(sum, 1.0, "box weights of a sample at {} sum to {}", u, sum);
+        }
+    }
+
+    #[test]
+    fn margin_covers_the_filter() {
+        let kinds = [
+            (FilterKind::Box, 0),
+            (FilterKind::Tent, 1),
+            (FilterKind::Gaussian, 1),
+            (FilterKind::Mitchell, 2),
+            (FilterKind::Lanczos, 3),
+        ];
+        for (kind, margin) in kinds {
+            let filter = Filter::new(kind);
+            assert_eq!(filter.margin(), margin, "{:?}", kind);
+
+            // Pixels past the margin get no weight from a sample in either corner of its pixel
+            let past = margin as f64 + 1.0;
+            for u in [0.0, 0.999_999] {
+                assert_eq!(filter.weight(u - (past + 0.5), 0.0), 0.0, "{:?}", kind);
+                assert_eq!(filter.weight(u + past - 0.5, 0.0), 0.0, "{:?}", kind);
+            }
+        }
+        let wide = Filter {
+            kind: FilterKind::Box,
+            radius: 1.5,
+        };
+        assert_eq!(wide.margin(), 1);
+    }
+}
@@ -2,8 +2,26 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("Failed to write to PPM file: {0}")]
+    #[error("Failed to write image: {0}")]
     FileWriteError(#[from] std::io::Error),
+
+    #[error("Failed to encode PNG image: {0}")]
+    PngEncodeError(#[from] png::EncodingError),
+
+    #[error("Render was cancelled")]
+    Cancelled,
+
+    #[error("Image is incomplete, only {rows} of {height} rows were written")]
+    IncompleteImage { rows: u32, height: u32 },
+
+    #[error("Unknown image format for {0}, expected a .ppm or .png file")]
+    UnknownImageFormat(String),
+
+    #[error("Unknown ID buffer format for {0}, expected a .pgm or .png file")]
+    UnknownIdFormat(String),
+
+    #[error("ID {0} doesn't fit in a 16 bit ID buffer")]
+    TooManyIds(u32),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
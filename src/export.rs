@@ -0,0 +1,302 @@
+use std::{collections::HashMap, io::Write};
+
+use crate::{texture::linear_to_srgb, Mesh, Result, Triangle, Vec3};
+
+/// Hands out indices for values, reusing the index of values already seen
+struct IndexCache<K> {
+    indices: HashMap<K, usize>,
+}
+
+impl<K: std::hash::Hash + Eq> IndexCache<K> {
+    fn new() -> Self {
+        Self {
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Find the index of a value
+    /// # Returns
+    /// * The index, and whether the value is new and needs to be written
+    fn index(&mut self, key: K) -> (usize, bool) {
+        let next = self.indices.len();
+        let index = *self.indices.entry(key).or_insert(next);
+        (index, index == next)
+    }
+}
+
+/// The normal of each corner, the flat normal repeated for flat shaded triangles
+fn corner_normals(trig: &Triangle) -> [Vec3; 3] {
+    if trig.smooth {
+        trig.normals
+    } else {
+        [trig.normal; 3]
+    }
+}
+
+/// Write meshes as an OBJ file
+/// # Arguments
+/// * 'meshes' - The meshes to write, each becomes an object named 'mesh<index>'
+/// * 'output' - Where to write the file
+/// # Notes
+/// * Positions, normals and texture coordinates are written with full precision, straight from each mesh's shared vertex arrays
+/// * Smooth shaded faces are written in smoothing group 1, flat ones with smoothing off
+/// * Materials and vertex colors aren't written
+pub fn write_obj(meshes: &[Mesh], output: &mut dyn Write) -> Result<()> {
+    // Indices are shared by the whole file, so each mesh's start after the previous ones
+    let mut position_offset = 0;
+    let mut normal_offset = 0;
+    let mut uv_offset = 0;
+
+    for (index, mesh) in meshes.iter().enumerate() {
+        writeln!(output, "o mesh{}", index)?;
+        for p in mesh.positions.iter() {
+            writeln!(output, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        for n in mesh.normals.iter() {
+            writeln!(output, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+        for uv in mesh.uvs.iter() {
+            writeln!(output, "vt {} {}", uv[0], uv[1])?;
+        }
+
+        let mut smooth: Option<bool> = None;
+        for face in mesh.faces.iter() {
+            let normals = if face.smooth {
+                face.normals
+            } else {
+                [face.normal; 3]
+            };
+
+            // OBJ indices start at 1
+            let mut line = String::from("f");
+            for corner in 0..3 {
+                let p = position_offset + face.positions[corner] + 1;
+                let n = normal_offset + normals[corner] + 1;
+                match face.uvs {
+                    Some(uvs) => {
+                        let t = uv_offset + uvs[corner] + 1;
+                        line.push_str(&format!(" {}/{}/{}", p, t, n));
+                    }
+                    None => line.push_str(&format!(" {}//{}", p, n)),
+                }
+            }
+
+            // Only write the smoothing group when it changes
+            if smooth != Some(face.smooth) {
+                smooth = Some(face.smooth);
+                writeln!(output, "s {}", if face.smooth { "1" } else { "off" })?;
+            }
+            writeln!(output, "{}", line)?;
+        }
+
+        position_offset += mesh.positions.len();
+        normal_offset += mesh.normals.len();
+        uv_offset += mesh.uvs.len();
+    }
+
+    Ok(())
+}
+
+/// Write meshes as a single binary little endian PLY file
+/// # Arguments
+/// * 'meshes' - The meshes to write, merged into one
+/// * 'output' - Where to write the file
+/// # Notes
+/// * Every vertex has a position and normal, flat shaded triangles use their flat normal at each corner
+/// * Texture coordinates (s, t) and sRGB colors (red, green, blue) are added when any triangle has them
+/// * Corners sharing all of their values are written as one vertex
+pub fn write_ply(meshes: &[Mesh], output: &mut dyn Write) -> Result<()> {
+    let triangles = || meshes.iter().flat_map(|mesh| mesh.triangles());
+    let has_uvs = meshes
+        .iter()
+        .any(|mesh| mesh.faces.iter().any(|f| f.uvs.is_some()));
+    let has_colors = meshes
+        .iter()
+        .any(|mesh| mesh.faces.iter().any(|f| f.colors.is_some()));
+
+    // Build the vertex and face lists first, the header needs their counts
+    let mut cache = IndexCache::new();
+    let mut vertices: Vec<u8> = Vec::new();
+    let mut faces: Vec<u8> = Vec::new();
+    let mut vertex_count = 0;
+    let mut face_count = 0;
+
+    for trig in triangles() {
+        let normal = corner_normals(&trig);
+        faces.push(3);
+
+        for corner in 0..3 {
+            let point = trig.points[corner];
+            let uv = trig.uvs.map(|uvs| uvs[corner]).unwrap_or([0.0, 0.0]);
+            let color = trig
+                .colors
+                .map(|colors| colors[corner])
+                .unwrap_or(Vec3::new(1.0, 1.0, 1.0));
+
+            // Properties are stored as f32 and u8, compare the values as written
+            let mut values: Vec<f32> = vec![
+                point.x as f32,
+                point.y as f32,
+                point.z as f32,
+                normal[corner].x as f32,
+                normal[corner].y as f32,
+                normal[corner].z as f32,
+            ];
+            if has_uvs {
+                values.extend([uv[0] as f32, uv[1] as f32]);
+            }
+            let mut rgb: Vec<u8> = Vec::new();
+            if has_colors {
+                rgb.extend(
+                    [color.x, color.y, color.z]
+                        .map(|c| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8),
+                );
+            }
+
+            let key = (
+                values.iter().map(|v| v.to_bits()).collect::<Vec<u32>>(),
+                rgb.clone(),
+            );
+            let (index, new) = cache.index(key);
+            if new {
+                for value in values {
+                    vertices.extend(value.to_le_bytes());
+                }
+                vertices.extend(rgb);
+                vertex_count += 1;
+            }
+            faces.extend((index as u32).to_le_bytes());
+        }
+        face_count += 1;
+    }
+
+    let mut header = String::from("ply\nformat binary_little_endian 1.0\n");
+    header.push_str(&format!("element vertex {}\n", vertex_count));
+    for property in ["x", "y", "z", "nx", "ny", "nz"] {
+        header.push_str(&format!("property float {}\n", property));
+    }
+    if has_uvs {
+        header.push_str("property float s\nproperty float t\n");
+    }
+    if has_colors {
+        header.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\n");
+    }
+    header.push_str(&format!("element face {}\n", face_count));
+    header.push_str("property list uchar uint vertex_indices\nend_header\n");
+
+    output.write_all(header.as_bytes())?;
+    output.write_all(&vertices)?;
+    output.write_all(&faces)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::{
+        load_mesh, load_ply, vec3::unit_vector, Instance, MeshError, RayTracerConfig, Transform,
+        Triangle,
+    };
+
+    /// Write meshes to a temporary file and load them back
+    fn round_trip(
+        name: &str,
+        meshes: &[Mesh],
+        write: fn(&[Mesh], &mut dyn Write) -> Result<()>,
+        load: fn(&str, bool) -> std::result::Result<Mesh, MeshError>,
+    ) -> Mesh {
+        let dir = TempDir::new(&format!("export_{}", name));
+        let mut data = Vec::new();
+        write(meshes, &mut data).unwrap();
+        load(&dir.write(name, data), true).unwrap()
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-6, "{:?} != {:?}", a, b);
+    }
+
+    /// A smooth shaded octahedron with texture coordinates
+    fn octahedron() -> Mesh {
+        let corners = [
+            Vec3::new(1.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.5),
+            Vec3::new(-1.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.5),
+        ];
+        let mut triangles = Vec::new();
+        for (i, &a) in corners.iter().enumerate() {
+            let b = corners[(i + 1) % 4];
+            for pole in [Vec3::new(0.0, 1.5, 0.0), Vec3::new(0.0, -1.5, 0.0)] {
+                let points = if pole.y > 0.0 {
+                    [a, b, pole]
+                } else {
+                    [b, a, pole]
+                };
+                let mut trig =
+                    Triangle::new(points[0], points[1], points[2], unit_vector(a + b + pole));
+                trig.smooth = true;
+                trig.normals = points.map(unit_vector);
+                trig.uvs = Some(points.map(|p| [0.5 + p.x / 3.0, 0.5 + p.y / 3.0]));
+                triangles.push(trig);
+            }
+        }
+        Mesh::new_mesh(triangles)
+    }
+
+    /// Compare the triangles of two meshes corner by corner
+    fn assert_same_triangles(loaded: &Mesh, original: &Mesh) {
+        assert_eq!(loaded.faces.len(), original.faces.len());
+        for (a, b) in loaded.triangles().zip(original.triangles()) {
+            for corner in 0..3 {
+                assert_close(a.points[corner], b.points[corner]);
+                assert_close(corner_normals(&a)[corner], corner_normals(&b)[corner]);
+                let (ua, ub) = (a.uvs.unwrap()[corner], b.uvs.unwrap()[corner]);
+                assert!((ua[0] - ub[0]).abs() < 1e-6 && (ua[1] - ub[1]).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn obj_round_trips_through_load_mesh() {
+        let sphere = octahedron();
+        let loaded = round_trip(
+            "sphere.obj",
+            std::slice::from_ref(&sphere),
+            write_obj,
+            load_mesh,
+        );
+        assert_same_triangles(&loaded, &sphere);
+    }
+
+    #[test]
+    fn ply_round_trips_through_load_ply() {
+        let sphere = octahedron();
+        let loaded = round_trip(
+            "sphere.ply",
+            std::slice::from_ref(&sphere),
+            write_ply,
+            load_ply,
+        );
+        assert_same_triangles(&loaded, &sphere);
+    }
+
+    #[test]
+    fn instances_are_exported_in_world_space() {
+        let mut ray_tracer = RayTracerConfig::default().build();
+        let offset = Vec3::new(0.0, 2.0, -5.0);
+        ray_tracer.add_instance(Instance::new(
+            Arc::new(octahedron()),
+            Transform::translate(offset),
+        ));
+
+        let meshes = ray_tracer.meshes();
+        let loaded = round_trip("instance.obj", &meshes, write_obj, load_mesh);
+        let mut expected = octahedron();
+        expected.translate(offset);
+        assert_same_triangles(&loaded, &expected);
+    }
+}
@@ -0,0 +1,428 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ::gltf::{
+    buffer, camera::Projection, image, image::Format, khr_lights_punctual::Kind, mesh::Mode,
+    scene::Node,
+};
+
+use crate::{
+    graph,
+    material::{MaterialEnum, Pbr},
+    mesh::{Face, MeshError},
+    texture::srgb_to_linear,
+    triangle::geometric_normal,
+    vec3::unit_vector,
+    CameraSettings, Light, LightKind, Mat4, Mesh, Texture, Transform, Vec3,
+};
+
+/// A mesh placed in a glTF scene, already transformed to world space
+#[derive(Clone, Debug)]
+pub struct GltfMesh {
+    /// Name of the node, falling back to the mesh's name
+    pub name: String,
+    /// The node's triangles, with one material table entry per glTF material it uses
+    pub mesh: Mesh,
+}
+
+/// A perspective camera placed in a glTF scene
+#[derive(Clone, Debug)]
+pub struct GltfCamera {
+    /// Name of the node, falling back to the camera's name
+    pub name: String,
+    /// Position, orientation and field of view to build the ray tracer with
+    pub settings: CameraSettings,
+    /// Width / height the camera was authored for, if given
+    pub aspect_ratio: Option<f64>,
+}
+
+/// A punctual light placed in a glTF scene
+#[derive(Clone, Debug)]
+pub struct GltfLight {
+    /// Name of the node, falling back to the light's name
+    pub name: String,
+    /// The light in world space
+    pub light: Light,
+}
+
+/// Everything imported from a glTF file
+#[derive(Clone, Debug, Default)]
+pub struct GltfScene {
+    /// One mesh per node with a mesh
+    pub meshes: Vec<GltfMesh>,
+    /// One camera per node with a perspective camera
+    pub cameras: Vec<GltfCamera>,
+    /// One light per node with a KHR_lights_punctual light
+    pub lights: Vec<GltfLight>,
+    /// The node hierarchy, with meshes shared between the nodes using them
+    pub root: graph::Node,
+}
+
+/// Load a glTF 2.0 file (.gltf with its .bin and images, or .glb)
+/// # Arguments
+/// * 'path' - Path of a glTF file
+/// # Returns
+/// * The meshes, cameras and lights of the file's default scene with node transforms applied, and its node hierarchy
+/// # Notes
+/// * Materials become Pbr materials with base color, metallic-roughness, normal and emissive textures
+/// * Only the first set of texture coordinates is used
+/// * Nodes scaled to 0 are skipped together with their children
+pub fn load_gltf(path: &str) -> Result<GltfScene, MeshError> {
+    let (document, buffers, images) = ::gltf::import(path).map_err(|source| MeshError::Gltf {
+        path: path.to_string(),
+        source,
+    })?;
+
+    let mut importer = Importer {
+        path,
+        buffers: &buffers,
+        images: &images,
+        meshes: HashMap::new(),
+        textures: HashMap::new(),
+        scene: GltfScene::default(),
+    };
+
+    // Files without a default scene are expected to show their first one
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        let mut root = graph::Node::new(scene.name().unwrap_or("root"));
+        for node in scene.nodes() {
+            if let Some(node) = importer.visit(node, &Transform::identity())? {
+                root.children.push(node);
+            }
+        }
+        importer.scene.root = root;
+    }
+
+    Ok(importer.scene)
+}
+
+/// State shared while walking the node hierarchy
+struct Importer<'a> {
+    path: &'a str,
+    buffers: &'a [buffer::Data],
+    images: &'a [image::Data],
+    /// Converted meshes by mesh index
+    meshes: HashMap<usize, Arc<Mesh>>,
+    /// Converted textures by image index and whether they're sRGB encoded
+    textures: HashMap<(usize, bool), Arc<Texture>>,
+    scene: GltfScene,
+}
+
+impl Importer<'_> {
+    fn invalid(&self, reason: String) -> MeshError {
+        MeshError::Invalid {
+            path: self.path.to_string(),
+            reason,
+        }
+    }
+
+    /// Import a node and its children
+    /// # Arguments
+    /// * 'node' - The node to import
+    /// * 'parent' - World transform of the node's parent
+    /// # Returns
+    /// * The scene graph node, None if its transform can't be inverted
+    fn visit(&mut self, node: Node, parent: &Transform) -> Result<Option<graph::Node>, MeshError> {
+        let name = |fallback: Option<&str>| {
+            node.name()
+                .or(fallback)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("node {}", node.index()))
+        };
+
+        // A node scaled to 0 can't be seen, and neither can its children
+        let local = Mat4::from_columns(
+            node.transform()
+                .matrix()
+                .map(|column| column.map(f64::from)),
+        );
+        let Some(local) = Transform::from_matrix(local) else {
+            return Ok(None);
+        };
+        let world = local.then(parent);
+        let mut result = graph::Node::new(&name(None)).with_transform(local);
+
+        if let Some(mesh) = node.mesh() {
+            let name = name(mesh.name());
+            let shared = self.mesh(mesh)?;
+
+            let mut mesh = (*shared).clone();
+            mesh.transform(&world);
+            self.scene.meshes.push(GltfMesh { name, mesh });
+            result.mesh = Some(shared);
+        }
+
+        // Orthographic cameras can't be represented by the camera
+        if let Some(camera) = node.camera() {
+            if let Projection::Perspective(perspective) = camera.projection() {
+                let settings = CameraSettings {
+                    position: Vec3::new(0.0, 0.0, 0.0),
+                    look_at: Vec3::new(0.0, 0.0, -1.0),
+                    up: Vec3::new(0.0, 1.0, 0.0),
+                    vertical_fov: (perspective.yfov() as f64).to_degrees(),
+                };
+                let position = world.transform_point(settings.position);
+                self.scene.cameras.push(GltfCamera {
+                    name: name(camera.name()),
+                    settings: CameraSettings {
+                        position,
+                        look_at: position + unit_vector(world.transform_vector(settings.look_at)),
+                        up: unit_vector(world.transform_vector(settings.up)),
+                        ..settings
+                    },
+                    aspect_ratio: perspective.aspect_ratio().map(f64::from),
+                });
+                result.camera = Some(settings);
+            }
+        }
+
+        if let Some(light) = node.light() {
+            let kind = match light.kind() {
+                Kind::Directional => LightKind::Directional,
+                Kind::Point => LightKind::Point,
+                Kind::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                } => LightKind::Spot {
+                    inner_cone_angle: inner_cone_angle as f64,
+                    outer_cone_angle: outer_cone_angle as f64,
+                },
+            };
+            let [r, g, b] = light.color();
+            let name = name(light.name());
+            // glTF lights sit at the node's origin and shine down its -z axis
+            let light = Light {
+                kind,
+                color: Vec3::new(r as f64, g as f64, b as f64),
+                intensity: light.intensity() as f64,
+                range: light.range().map(f64::from),
+                position: Vec3::new(0.0, 0.0, 0.0),
+                direction: Vec3::new(0.0, 0.0, -1.0),
+            };
+            self.scene.lights.push(GltfLight {
+                name,
+                light: light.transformed(&world),
+            });
+            result.light = Some(light);
+        }
+
+        for child in node.children() {
+            if let Some(child) = self.visit(child, &world)? {
+                result.children.push(child);
+            }
+        }
+
+        Ok(Some(result))
+    }
+
+    /// Convert a glTF mesh to an object space mesh, shared by every node using it
+    fn mesh(&mut self, mesh: ::gltf::Mesh) -> Result<Arc<Mesh>, MeshError> {
+        if let Some(result) = self.meshes.get(&mesh.index()) {
+            return Ok(result.clone());
+        }
+        let index = mesh.index();
+        let mut result = Mesh::new_mesh(Vec::new());
+
+        // The same glTF material is only added to the material table once
+        let mut materials: HashMap<usize, usize> = HashMap::new();
+
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+
+            let positions: Vec<Vec3> = reader
+                .read_positions()
+                .ok_or_else(|| self.invalid("mesh primitive has no positions".to_string()))?
+                .map(|[x, y, z]| Vec3::new(x as f64, y as f64, z as f64))
+                .collect();
+            let normals: Option<Vec<Vec3>> = reader.read_normals().map(|normals| {
+                normals
+                    .map(|[x, y, z]| Vec3::new(x as f64, y as f64, z as f64))
+                    .collect()
+            });
+
+            // glTF's v axis points down the image, flip it to match OBJ
+            let uvs: Option<Vec<[f64; 2]>> = reader.read_tex_coords(0).map(|uvs| {
+                uvs.into_f32()
+                    .map(|[u, v]| [u as f64, 1.0 - v as f64])
+                    .collect()
+            });
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..positions.len()).collect(),
+            };
+            if let Some(index) = indices.iter().find(|i| **i >= positions.len()) {
+                return Err(self.invalid(format!(
+                    "mesh index {} is out of range, primitive has {} vertices",
+                    index,
+                    positions.len()
+                )));
+            }
+
+            // Points and lines have no surface to hit
+            let corners: Vec<[usize; 3]> = match primitive.mode() {
+                Mode::Triangles => indices
+                    .chunks_exact(3)
+                    .map(|c| [c[0], c[1], c[2]])
+                    .collect(),
+                Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+                    .map(|i| {
+                        // Every other triangle in a strip has reversed winding
+                        if i % 2 == 0 {
+                            [indices[i], indices[i + 1], indices[i + 2]]
+                        } else {
+                            [indices[i + 1], indices[i], indices[i + 2]]
+                        }
+                    })
+                    .collect(),
+                Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+                    .map(|i| [indices[0], indices[i], indices[i + 1]])
+                    .collect(),
+                _ => continue,
+            };
+
+            let material = match primitive.material().index() {
+                Some(index) => match materials.get(&index) {
+                    Some(material) => Some(*material),
+                    None => {
+                        let material = self.material(primitive.material())?;
+                        let material = result.add_material(material);
+                        materials.insert(index, material);
+                        Some(material)
+                    }
+                },
+                None => None,
+            };
+
+            // Primitives share the mesh's vertex arrays, so their indices start after the previous ones
+            let position_offset = result.positions.len();
+            let normal_offset = result.normals.len();
+            let uv_offset = result.uvs.len();
+            result.positions.extend(positions);
+            if let Some(normals) = &normals {
+                result.normals.extend(normals);
+            }
+            if let Some(uvs) = &uvs {
+                result.uvs.extend(uvs);
+            }
+
+            for corners in corners {
+                result.normals.push(geometric_normal(
+                    corners.map(|i| result.positions[position_offset + i]),
+                ));
+                let mut face = Face::new(
+                    corners.map(|i| position_offset + i),
+                    result.normals.len() - 1,
+                );
+
+                // Without normals glTF meshes are flat shaded
+                if normals.is_some() {
+                    face.smooth = true;
+                    face.normals = corners.map(|i| normal_offset + i);
+                }
+                if uvs.is_some() {
+                    face.uvs = Some(corners.map(|i| uv_offset + i));
+                }
+                face.material = material;
+                result.faces.push(face);
+            }
+        }
+
+        let result = Arc::new(result);
+        self.meshes.insert(index, result.clone());
+        Ok(result)
+    }
+
+    /// Convert a glTF metallic-roughness material
+    fn material(&mut self, material: ::gltf::Material) -> Result<MaterialEnum, MeshError> {
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let mut result = Pbr::new(
+            Vec3::new(r as f64, g as f64, b as f64),
+            pbr.metallic_factor() as f64,
+            pbr.roughness_factor() as f64,
+        );
+
+        if let Some(info) = pbr.base_color_texture() {
+            result.base_color_texture = Some(self.texture(info.texture(), true)?);
+        }
+        if let Some(info) = pbr.metallic_roughness_texture() {
+            result.metallic_roughness_texture = Some(self.texture(info.texture(), false)?);
+        }
+        if let Some(normal) = material.normal_texture() {
+            result.normal_texture = Some(self.texture(normal.texture(), false)?);
+            result.normal_scale = normal.scale() as f64;
+        }
+
+        let [r, g, b] = material.emissive_factor();
+        result.emissive = Vec3::new(r as f64, g as f64, b as f64);
+        if let Some(info) = material.emissive_texture() {
+            result.emissive_texture = Some(self.texture(info.texture(), true)?);
+        }
+
+        Ok(MaterialEnum::Pbr(result))
+    }
+
+    /// Convert a glTF texture's image, reusing it if it was already converted
+    /// # Arguments
+    /// * 'texture' - The glTF texture
+    /// * 'srgb' - Whether the image holds sRGB encoded colors rather than linear data
+    fn texture(&mut self, texture: ::gltf::Texture, srgb: bool) -> Result<Arc<Texture>, MeshError> {
+        let index = texture.source().index();
+        if let Some(texture) = self.textures.get(&(index, srgb)) {
+            return Ok(texture.clone());
+        }
+
+        let image = self
+            .images
+            .get(index)
+            .ok_or_else(|| self.invalid(format!("image {} was not loaded", index)))?;
+
+        let (channels, bytes) = match image.format {
+            Format::R8 => (1, 1),
+            Format::R8G8 => (2, 1),
+            Format::R8G8B8 => (3, 1),
+            Format::R8G8B8A8 => (4, 1),
+            Format::R16 => (1, 2),
+            Format::R16G16 => (2, 2),
+            Format::R16G16B16 => (3, 2),
+            Format::R16G16B16A16 => (4, 2),
+            Format::R32G32B32FLOAT => (3, 4),
+            Format::R32G32B32A32FLOAT => (4, 4),
+        };
+
+        // Read a channel as 0-1, float images are already linear
+        let read = |data: &[u8]| -> f64 {
+            let value = match bytes {
+                1 => data[0] as f64 / 255.0,
+                2 => u16::from_ne_bytes([data[0], data[1]]) as f64 / 65535.0,
+                _ => return f32::from_ne_bytes([data[0], data[1], data[2], data[3]]) as f64,
+            };
+            if srgb {
+                srgb_to_linear(value)
+            } else {
+                value
+            }
+        };
+
+        let pixels = image
+            .pixels
+            .chunks_exact(channels * bytes)
+            .map(|pixel| {
+                let channel = |i: usize| read(&pixel[i * bytes..(i + 1) * bytes]);
+                match channels {
+                    1 => Vec3::new(channel(0), channel(0), channel(0)),
+                    2 => Vec3::new(channel(0), channel(1), 0.0),
+                    _ => Vec3::new(channel(0), channel(1), channel(2)),
+                }
+            })
+            .collect();
+
+        let texture = Texture::new(image.width, image.height, pixels)
+            .map_err(|e| self.invalid(e.to_string()))?;
+        let texture = Arc::new(texture);
+        self.textures.insert((index, srgb), texture.clone());
+        Ok(texture)
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    material::MaterialEnum, vec3::unit_vector, CameraSettings, Instance, Light, Mesh, Transform,
+};
+
+/// A node of a scene graph, placed relative to its parent
+/// # Notes
+/// * Moving a node moves all of its children with it
+/// * Geometry, cameras and lights are in the node's local space until the graph is flattened
+#[derive(Clone, Debug)]
+pub struct Node {
+    /// Name used to find the node
+    pub name: String,
+    /// Transform from the node's local space to its parent's space
+    pub transform: Transform,
+    /// Geometry drawn at the node, can be shared with other nodes
+    pub mesh: Option<Arc<Mesh>>,
+    /// Material used for every face of the node's mesh instead of the mesh's materials, if set, can be shared with other nodes
+    pub material: Option<Arc<MaterialEnum>>,
+    /// Camera placed at the node
+    pub camera: Option<CameraSettings>,
+    /// Light placed at the node, its position and direction are in local space
+    pub light: Option<Light>,
+    /// Nodes placed relative to this one
+    pub children: Vec<Node>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self::new("root")
+    }
+}
+
+impl Node {
+    /// Create a new empty node
+    /// # Arguments
+    /// * 'name' - Name used to find the node
+    /// # Returns
+    /// * Node with an identity transform, no geometry, camera, light or children
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            transform: Transform::identity(),
+            mesh: None,
+            material: None,
+            camera: None,
+            light: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Place the node relative to its parent
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Draw a mesh at the node
+    pub fn with_mesh(mut self, mesh: Arc<Mesh>) -> Self {
+        self.mesh = Some(mesh);
+        self
+    }
+
+    /// Draw the node's mesh with a single material
+    pub fn with_material(mut self, material: impl Into<Arc<MaterialEnum>>) -> Self {
+        self.material = Some(material.into());
+        self
+    }
+
+    /// Place a camera at the node
+    pub fn with_camera(mut self, camera: CameraSettings) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    /// Place a light at the node
+    pub fn with_light(mut self, light: Light) -> Self {
+        self.light = Some(light);
+        self
+    }
+
+    /// Add a child node
+    pub fn with_child(mut self, child: Node) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Find a node by name, searching depth first starting with this node
+    pub fn find(&self, name: &str) -> Option<&Node> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(name))
+    }
+
+    /// Find a node by name to change it, searching depth first starting with this node
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut Node> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_mut(name))
+    }
+
+    /// Walk this node and all of its descendants, parents before children
+    /// # Arguments
+    /// * 'parent' - World transform of this node's parent, identity for the root
+    /// * 'f' - Called with each node and its world transform
+    pub fn visit(&self, parent: &Transform, f: &mut dyn FnMut(&Node, &Transform)) {
+        let world = self.transform.then(parent);
+        f(self, &world);
+        for child in self.children.iter() {
+            child.visit(&world, f);
+        }
+    }
+
+    /// Flatten the graph into world space instances, one for each node with a mesh
+    /// # Notes
+    /// * Nodes sharing a mesh share its bounding box, so each mesh is only measured once
+    pub fn instances(&self) -> Vec<Instance> {
+        let mut instances = Vec::new();
+        let mut first: HashMap<*const Mesh, Instance> = HashMap::new();
+        self.visit(&Transform::identity(), &mut |node, world| {
+            if let Some(mesh) = &node.mesh {
+                let mut instance = match first.get(&Arc::as_ptr(mesh)) {
+                    Some(first) => first.place(*world),
+                    None => {
+                        let instance = Instance::new(mesh.clone(), *world);
+                        first.insert(Arc::as_ptr(mesh), instance.clone());
+                        instance
+                    }
+                };
+                instance.material = node.material.clone();
+                instances.push(instance);
+            }
+        });
+        instances
+    }
+
+    /// Flatten the graph's cameras into world space
+    /// # Returns
+    /// * The name of the node and its camera, for each node with a camera
+    pub fn cameras(&self) -> Vec<(String, CameraSettings)> {
+        let mut cameras = Vec::new();
+        self.visit(&Transform::identity(), &mut |node, world| {
+            if let Some(camera) = node.camera {
+                let settings = CameraSettings {
+                    position: world.transform_point(camera.position),
+                    look_at: world.transform_point(camera.look_at),
+                    up: unit_vector(world.transform_vector(camera.up)),
+                    vertical_fov: camera.vertical_fov,
+                };
+                cameras.push((node.name.clone(), settings));
+            }
+        });
+        cameras
+    }
+
+    /// Flatten the graph's lights into world space
+    /// # Returns
+    /// * The name of the node and its light, for each node with a light
+    pub fn lights(&self) -> Vec<(String, Light)> {
+        let mut lights = Vec::new();
+        self.visit(&Transform::identity(), &mut |node, world| {
+            if let Some(light) = &node.light {
+                lights.push((node.name.clone(), light.transformed(world)));
+            }
+        });
+        lights
+    }
+}
@@ -1,5 +1,7 @@
 use crate::{
+    config::IdKind,
     material::{Diffuse, MaterialEnum},
+    vec3::{barycentric, unit_vector},
     Triangle, Vec3,
 };
 
@@ -14,6 +16,10 @@ pub struct Hit {
     pub t: f64,
     /// The triangle's material
     pub material: MaterialEnum,
+    /// ID of the mesh or instance the world gave it when it was added, None if it wasn't hit through the world
+    pub object_id: Option<u32>,
+    /// ID of the material the world gave it when its object was added, None if it wasn't hit through the world
+    pub material_id: Option<u32>,
 }
 
 impl Default for Hit {
@@ -29,12 +35,71 @@ impl Hit {
     /// * 'at' - Hit position (0,0,-500000000)
     /// * 't' - -1.0
     /// * 'material' - White diffuse material
+    /// * 'object_id, material_id' - None
     pub fn new() -> Hit {
         Hit {
             triangle: Triangle::new_empty(),
             at: Vec3::new(0.0, 0.0, -5000000000000.0),
             t: -1.0,
             material: MaterialEnum::Diffuse(Diffuse::new(Vec3::new(1.0, 1.0, 1.0))),
+            object_id: None,
+            material_id: None,
+        }
+    }
+}
+
+impl Hit {
+    /// The object or material ID of the hit
+    /// # Arguments
+    /// * 'kind' - Which of the two IDs to return
+    pub fn id(&self, kind: IdKind) -> Option<u32> {
+        match kind {
+            IdKind::Object => self.object_id,
+            IdKind::Material => self.material_id,
+        }
+    }
+
+    /// Calculate the vertex color at the hit position
+    /// # Returns
+    /// * The interpolated vertex color, or white if the triangle has no colors
+    pub fn vertex_color(&self) -> Vec3 {
+        match self.triangle.colors {
+            Some(colors) => {
+                let bary = barycentric(self.clone());
+                colors[0] * bary.x + colors[1] * bary.y + colors[2] * bary.z
+            }
+            None => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Calculate the texture coordinates at the hit position
+    /// # Returns
+    /// * The interpolated u,v, or None if the triangle has no uvs
+    pub fn uv(&self) -> Option<[f64; 2]> {
+        let uvs = self.triangle.uvs?;
+        let bary = barycentric(self.clone());
+        Some([
+            uvs[0][0] * bary.x + uvs[1][0] * bary.y + uvs[2][0] * bary.z,
+            uvs[0][1] * bary.x + uvs[1][1] * bary.y + uvs[2][1] * bary.z,
+        ])
+    }
+
+    /// Calculate the shading normal at the hit position
+    /// # Returns
+    /// * The interpolated normal if the triangle is smooth shaded, otherwise its single normal
+    pub fn normal(&self) -> Vec3 {
+        if self.triangle.smooth {
+            // Calculate the barycentric coordinates
+            let bary = barycentric(self.clone());
+
+            // Calculate the interpolated normal
+            unit_vector(
+                self.triangle.normals[0] * bary.x
+                    + self.triangle.normals[1] * bary.y
+                    + self.triangle.normals[2] * bary.z,
+            )
+        } else {
+            self.triangle.normal
         }
     }
 }
@@ -0,0 +1,386 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{error::Error, Result, Tile, TileSink};
+
+/// File formats a rendered image can be written as
+/// * 'Ppm' - Plain text PPM, as written by run_sequential and run_parallel
+/// * 'Png' - 8 bit RGB PNG
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    /// Pick the format from a file's extension
+    /// # Returns
+    /// * The format, None if the extension isn't known
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path)
+            .extension()?
+            .to_string_lossy()
+            .to_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+/// A rendered image
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// 8 bit r,g,b values, row by row starting at the top left
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    /// Create a black image
+    /// # Arguments
+    /// * 'width, height' - Size of the image in pixels
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0, 0, 0]; (width * height) as usize],
+        }
+    }
+
+    /// Write the image in a format
+    /// # Arguments
+    /// * 'output' - Where the encoded image is written
+    /// * 'format' - File format to encode the image as
+    pub fn write(&self, output: &mut dyn Write, format: ImageFormat) -> Result<()> {
+        match format {
+            ImageFormat::Ppm => self.write_ppm(output),
+            ImageFormat::Png => self.write_png(output),
+        }
+    }
+
+    /// Write the image to a file, picking the format from its extension
+    /// # Arguments
+    /// * 'path' - Path of the file, ending in .ppm or .png
+    pub fn save(&self, path: &str) -> Result<()> {
+        let format = ImageFormat::from_path(path)
+            .ok_or_else(|| Error::UnknownImageFormat(path.to_string()))?;
+        let mut output = BufWriter::new(File::create(path)?);
+        self.write(&mut output, format)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    /// Write the image as a plain text PPM
+    pub fn write_ppm(&self, output: &mut dyn Write) -> Result<()> {
+        output.write_all(format!("P3\n{} {}\n255\n", self.width, self.height).as_bytes())?;
+        for [r, g, b] in self.pixels.iter() {
+            output.write_all(format!("{} {} {}\n", r, g, b).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Write the image as a PNG
+    pub fn write_png(&self, output: &mut dyn Write) -> Result<()> {
+        let mut encoder = png::Encoder::new(output, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(self.pixels.as_flattened())?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Object or material IDs of a rendered image, see RayTracer::render_ids
+pub struct IdBuffer {
+    pub width: u32,
+    pub height: u32,
+    /// The ID seen through each pixel, None where nothing was hit, row by row starting at the top left
+    pub ids: Vec<Option<u32>>,
+}
+
+impl IdBuffer {
+    /// Write the IDs to a file, picking the format from its extension
+    /// # Arguments
+    /// * 'path' - Path of the file, ending in .pgm for plain text PGM or .png for 16 bit grayscale PNG
+    /// # Notes
+    /// * Pixels hold the ID plus 1, so 0 is left for pixels where nothing was hit
+    /// * Error::TooManyIds if an ID doesn't fit in 16 bits
+    pub fn save(&self, path: &str) -> Result<()> {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let png = match extension.as_deref() {
+            Some("pgm") => false,
+            Some("png") => true,
+            _ => return Err(Error::UnknownIdFormat(path.to_string())),
+        };
+
+        // Check the IDs before creating the file
+        let values = self.values()?;
+        let mut output = BufWriter::new(File::create(path)?);
+        if png {
+            let mut encoder = png::Encoder::new(&mut output, self.width, self.height);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Sixteen);
+
+            let mut writer = encoder.write_header()?;
+            let data: Vec<u8> = values
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect();
+            writer.write_image_data(&data)?;
+            writer.finish()?;
+        } else {
+            output.write_all(format!("P2\n{} {}\n65535\n", self.width, self.height).as_bytes())?;
+            for row in values.chunks(self.width.max(1) as usize) {
+                let row: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+                output.write_all(format!("{}\n", row.join(" ")).as_bytes())?;
+            }
+        }
+        output.flush()?;
+        Ok(())
+    }
+
+    /// The 16 bit value of each pixel, the ID plus 1 or 0 where nothing was hit
+    fn values(&self) -> Result<Vec<u16>> {
+        self.ids
+            .iter()
+            .map(|id| match id {
+                Some(id) => id
+                    .checked_add(1)
+                    .and_then(|value| u16::try_from(value).ok())
+                    .ok_or(Error::TooManyIds(*id)),
+                None => Ok(0),
+            })
+            .collect()
+    }
+}
+
+/// Copy finished tiles into the image, used as a framebuffer
+impl TileSink for Image {
+    fn write_tile(&mut self, tile: &Tile) -> Result<()> {
+        let area = tile.area;
+        for (row, pixels) in tile.pixels.chunks(area.width as usize).enumerate() {
+            let start = ((area.y + row as u32) * self.width + area.x) as usize;
+            self.pixels[start..start + pixels.len()].copy_from_slice(pixels);
+        }
+        Ok(())
+    }
+}
+
+/// Encoder an ImageWriter writes finished rows to
+enum RowEncoder<'a> {
+    Ppm(&'a mut dyn Write),
+    /// Compresses the rows as they arrive, the png crate's stream writer needs an output it owns,
+    /// so it writes to a pending buffer which is moved to the output after every row
+    Png {
+        stream: Box<png::StreamWriter<'static, PendingBytes>>,
+        pending: PendingBytes,
+        output: &'a mut dyn Write,
+    },
+}
+
+/// Encoded bytes which aren't written to the output yet, shared with the PNG stream writer
+#[derive(Clone, Default)]
+struct PendingBytes(Rc<RefCell<Vec<u8>>>);
+
+impl PendingBytes {
+    /// Move the pending bytes to the output
+    fn drain(&self, output: &mut dyn Write) -> std::io::Result<()> {
+        let mut bytes = self.0.borrow_mut();
+        output.write_all(&bytes)?;
+        bytes.clear();
+        Ok(())
+    }
+}
+
+impl Write for PendingBytes {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes an image while it's being rendered, as soon as its rows are finished
+/// # Notes
+/// * PPM files only keep the rows which aren't finished yet in memory, so scanline tile order uses the least memory
+/// * PNG files are also compressed row by row, so both formats keep only the unfinished rows
+pub struct ImageWriter<'a> {
+    encoder: RowEncoder<'a>,
+    width: u32,
+    height: u32,
+    /// Rows from next_row on which aren't written yet, with the number of pixels filled in
+    rows: VecDeque<(Vec<[u8; 3]>, u32)>,
+    next_row: u32,
+}
+
+impl<'a> ImageWriter<'a> {
+    /// Start writing an image
+    /// # Arguments
+    /// * 'output' - Where the encoded image is written
+    /// * 'width, height' - Size of the image in pixels
+    /// * 'format' - File format to encode the image as
+    pub fn new(
+        output: &'a mut dyn Write,
+        width: u32,
+        height: u32,
+        format: ImageFormat,
+    ) -> Result<Self> {
+        let encoder = match format {
+            ImageFormat::Ppm => {
+                output.write_all(format!("P3\n{} {}\n255\n", width, height).as_bytes())?;
+                RowEncoder::Ppm(output)
+            }
+            ImageFormat::Png => {
+                let pending = PendingBytes::default();
+                let mut encoder = png::Encoder::new(pending.clone(), width, height);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                let stream = Box::new(encoder.write_header()?.into_stream_writer()?);
+                pending.drain(output)?;
+                RowEncoder::Png {
+                    stream,
+                    pending,
+                    output,
+                }
+            }
+        };
+
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            rows: VecDeque::new(),
+            next_row: 0,
+        })
+    }
+
+    /// Finish the file
+    /// # Returns
+    /// * Error::IncompleteImage if some rows never got all of their pixels
+    pub fn finish(self) -> Result<()> {
+        if self.next_row < self.height {
+            return Err(Error::IncompleteImage {
+                rows: self.next_row,
+                height: self.height,
+            });
+        }
+        match self.encoder {
+            RowEncoder::Ppm(output) => output.flush()?,
+            RowEncoder::Png {
+                stream,
+                pending,
+                output,
+            } => {
+                // The end chunk is written when the stream writer is dropped
+                (*stream).finish()?;
+                pending.drain(output)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a finished row
+    fn write_row(&mut self, pixels: &[[u8; 3]]) -> Result<()> {
+        match &mut self.encoder {
+            RowEncoder::Ppm(output) => {
+                for [r, g, b] in pixels {
+                    output.write_all(format!("{} {} {}\n", r, g, b).as_bytes())?;
+                }
+            }
+            RowEncoder::Png {
+                stream,
+                pending,
+                output,
+            } => {
+                stream.write_all(pixels.as_flattened())?;
+                pending.drain(*output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TileSink for ImageWriter<'_> {
+    fn write_tile(&mut self, tile: &Tile) -> Result<()> {
+        let area = tile.area;
+        for (row, pixels) in tile.pixels.chunks(area.width as usize).enumerate() {
+            // Rows are only created once a tile reaches them
+            let index = (area.y + row as u32 - self.next_row) as usize;
+            while self.rows.len() <= index {
+                self.rows
+                    .push_back((vec![[0, 0, 0]; self.width as usize], 0));
+            }
+            let (buffer, filled) = &mut self.rows[index];
+            buffer[area.x as usize..area.x as usize + pixels.len()].copy_from_slice(pixels);
+            *filled += area.width;
+        }
+
+        // Write out every finished row at the top
+        while matches!(self.rows.front(), Some((_, filled)) if *filled == self.width) {
+            if let Some((buffer, _)) = self.rows.pop_front() {
+                self.write_row(&buffer)?;
+                self.next_row += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Region;
+
+    #[test]
+    fn streamed_png_matches_the_whole_image() {
+        let (width, height) = (5, 4);
+        let mut image = Image::new(width, height);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            *pixel = [i as u8 * 10, 255 - i as u8, (i % 3) as u8 * 100];
+        }
+
+        // Tiles arrive out of order, so rows have to wait for their other half
+        let tiles = [(3, 0, 2, 4), (0, 2, 3, 2), (0, 0, 3, 2)].map(|(x, y, w, h)| Tile {
+            area: Region {
+                x,
+                y,
+                width: w,
+                height: h,
+            },
+            pixels: (y..y + h)
+                .flat_map(|row| (x..x + w).map(move |column| (row * width + column) as usize))
+                .map(|i| image.pixels[i])
+                .collect(),
+        });
+
+        let mut streamed = Vec::new();
+        let mut writer = ImageWriter::new(&mut streamed, width, height, ImageFormat::Png).unwrap();
+        for tile in &tiles {
+            writer.write_tile(tile).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(&streamed[streamed.len() - 8..streamed.len() - 4], b"IEND");
+
+        let decoder = png::Decoder::new(streamed.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut decoded).unwrap();
+        assert_eq!(decoded, image.pixels.as_flattened());
+    }
+}
@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use crate::{material::MaterialEnum, Hit, Mesh, Ray, Transform, Vec3};
+
+/// A placement of a shared mesh in the world
+/// # Notes
+/// * Many instances can share one mesh, only the Arc is cloned
+/// * Rays are transformed into the mesh's object space instead of transforming the mesh
+#[derive(Clone, Debug)]
+pub struct Instance {
+    /// The shared mesh, in object space
+    mesh: Arc<Mesh>,
+    /// Bounding box of the mesh in object space, None if it has no faces or the transform is singular
+    bounds: Option<Aabb>,
+    /// Transform from object space to world space
+    pub transform: Transform,
+    /// Material used for every face instead of the mesh's materials, if set, can be shared with other instances
+    pub material: Option<Arc<MaterialEnum>>,
+}
+
+impl Instance {
+    /// Create a new instance of a mesh
+    /// # Arguments
+    /// * 'mesh' - The shared mesh
+    /// * 'transform' - Transform from the mesh's object space to world space
+    /// # Returns
+    /// * Instance drawn with the mesh's own materials
+    /// # Notes
+    /// * The mesh's bounding box is measured here, use place for more instances of the same mesh
+    /// * A singular transform, e.g. a scale of 0, collapses the mesh and the instance is never hit, see Transform::is_singular
+    pub fn new(mesh: Arc<Mesh>, transform: Transform) -> Self {
+        let bounds = Aabb::new(&mesh);
+        Self::with_bounds(mesh, bounds, transform)
+    }
+
+    /// Place another instance of the same mesh
+    /// # Arguments
+    /// * 'transform' - Transform from the mesh's object space to world space
+    /// # Returns
+    /// * Instance drawn with the mesh's own materials, sharing this instance's bounding box of the mesh
+    pub fn place(&self, transform: Transform) -> Self {
+        // The box is only dropped for a singular transform or a mesh without faces
+        let bounds = self.bounds.or_else(|| Aabb::new(&self.mesh));
+        Self::with_bounds(self.mesh.clone(), bounds, transform)
+    }
+
+    fn with_bounds(mesh: Arc<Mesh>, bounds: Option<Aabb>, transform: Transform) -> Self {
+        Self {
+            mesh,
+            // Without a box the instance is never hit, so no rays are moved by an infinite inverse
+            bounds: bounds.filter(|_| !transform.is_singular()),
+            transform,
+            material: None,
+        }
+    }
+
+    /// Draw every face of the instance with a single material
+    /// # Arguments
+    /// * 'material' - Material used instead of the mesh's materials, instances sharing an Arc share its material ID
+    pub fn with_material(mut self, material: impl Into<Arc<MaterialEnum>>) -> Self {
+        self.material = Some(material.into());
+        self
+    }
+
+    /// The shared mesh
+    pub fn mesh(&self) -> &Arc<Mesh> {
+        &self.mesh
+    }
+
+    /// Copy the shared mesh into world space
+    /// # Returns
+    /// * The mesh with the instance's transform applied, drawn with the instance's material if it has one
+    pub fn world_mesh(&self) -> Mesh {
+        let mut mesh = (*self.mesh).clone();
+        mesh.transform(&self.transform);
+        if let Some(material) = &self.material {
+            mesh.material = (**material).clone();
+            mesh.materials.clear();
+            for face in mesh.faces.iter_mut() {
+                face.material = None;
+            }
+        }
+        mesh
+    }
+
+    /// Calculate a box around the instance in world space
+    /// # Returns
+    /// * The transformed corners of the mesh's box boxed again, None if the mesh has no faces
+    pub(crate) fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.bounds?;
+        Aabb::around(bounds.corners().map(|p| self.transform.transform_point(p)))
+    }
+
+    /// Check if the instance has been hit by a ray
+    /// # Arguments
+    /// * 'r' - The incoming ray, in world space
+    /// # Returns
+    /// * A hit struct containing the closest hit triangle in world space and its properties
+    pub fn hit(&self, r: Ray) -> Hit {
+        // Affine transforms keep t the same, so the direction isn't normalized
+        let inverse = self.transform.inverse_matrix();
+        let local = Ray::new(
+            inverse.transform_point(r.origin),
+            inverse.transform_vector(r.direction),
+        );
+
+        match self.bounds {
+            Some(bounds) if bounds.hit(local) => {}
+            _ => return Hit::new(),
+        }
+
+        let mut hit = self.mesh.hit(local);
+        if hit.t <= 0.0 {
+            return hit;
+        }
+
+        // Move the hit triangle to world space, where the shading happens
+        let trig = &mut hit.triangle;
+        trig.points = trig.points.map(|p| self.transform.transform_point(p));
+        trig.normal = self.transform.transform_normal(trig.normal);
+        if trig.smooth {
+            trig.normals = trig.normals.map(|n| self.transform.transform_normal(n));
+        }
+
+        // Mirroring transforms flip the winding, swap two corners to keep it counter-clockwise
+        if self.transform.is_mirroring() {
+            trig.points.swap(1, 2);
+            trig.normals.swap(1, 2);
+            if let Some(uvs) = trig.uvs.as_mut() {
+                uvs.swap(1, 2);
+            }
+            if let Some(colors) = trig.colors.as_mut() {
+                colors.swap(1, 2);
+            }
+        }
+
+        hit.at = r.at(hit.t);
+        if let Some(material) = &self.material {
+            hit.material = (**material).clone();
+        }
+        hit
+    }
+}
+
+/// Axis aligned bounding box, used to skip meshes a ray can't hit
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Aabb {
+    pub(crate) min: Vec3,
+    pub(crate) max: Vec3,
+}
+
+impl Aabb {
+    /// Calculate the bounding box of the points used by a mesh's faces
+    pub(crate) fn new(mesh: &Mesh) -> Option<Self> {
+        Self::around(
+            mesh.faces
+                .iter()
+                .flat_map(|face| face.positions)
+                .map(|p| mesh.positions[p]),
+        )
+    }
+
+    /// Calculate the bounding box of some points, None if there are none
+    fn around(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |b, p| Aabb {
+                min: Vec3::new(b.min.x.min(p.x), b.min.y.min(p.y), b.min.z.min(p.z)),
+                max: Vec3::new(b.max.x.max(p.x), b.max.y.max(p.y), b.max.z.max(p.z)),
+            },
+        ))
+    }
+
+    /// The smallest box around both boxes
+    pub(crate) fn union(self, other: Aabb) -> Aabb {
+        Aabb::around([self.min, self.max, other.min, other.max]).unwrap_or(self)
+    }
+
+    /// The 8 corners of the box
+    pub(crate) fn corners(&self) -> [Vec3; 8] {
+        std::array::from_fn(|i| {
+            Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    /// Check if a ray passes through the box in front of its origin, using the slab method
+    fn hit(&self, r: Ray) -> bool {
+        let mut t_min = 0.0_f64;
+        let mut t_max = f64::INFINITY;
+        for (origin, direction, min, max) in [
+            (r.origin.x, r.direction.x, self.min.x, self.max.x),
+            (r.origin.y, r.direction.y, self.min.y, self.max.y),
+            (r.origin.z, r.direction.z, self.min.z, self.max.z),
+        ] {
+            // A ray parallel to the slab either always or never lies within it
+            if direction == 0.0 {
+                if origin < min || origin > max {
+                    return false;
+                }
+                continue;
+            }
+
+            let t0 = (min - origin) / direction;
+            let t1 = (max - origin) / direction;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Triangle;
+
+    /// A triangle facing +z, covering the origin
+    fn triangle_mesh() -> Arc<Mesh> {
+        Arc::new(Mesh::new_mesh(vec![Triangle::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        )]))
+    }
+
+    fn down_the_z_axis() -> Ray {
+        Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn singular_instance_is_never_hit() {
+        let flat = Transform::scale(Vec3::new(1.0, 0.0, 1.0));
+        assert!(flat.is_singular());
+        assert!(!Transform::scale(Vec3::new(1.0, 2.0, 3.0)).is_singular());
+
+        let instance = Instance::new(triangle_mesh(), flat);
+        assert!(instance.bounds().is_none());
+        let hit = instance.hit(down_the_z_axis());
+        assert!(hit.t <= 0.0);
+        assert!(!hit.at.x.is_nan() && !hit.at.y.is_nan() && !hit.at.z.is_nan());
+    }
+
+    #[test]
+    fn placed_instance_hits_like_a_new_one() {
+        let mesh = triangle_mesh();
+        let transform = Transform::translate(Vec3::new(0.0, 0.0, 2.0));
+        let first = Instance::new(mesh.clone(), Transform::identity());
+        let placed = first.place(transform);
+        let new = Instance::new(mesh, transform);
+
+        assert!(Arc::ptr_eq(placed.mesh(), new.mesh()));
+        let (placed_hit, new_hit) = (placed.hit(down_the_z_axis()), new.hit(down_the_z_axis()));
+        assert!((placed_hit.t - 3.0).abs() < 1e-9);
+        assert!((placed_hit.t - new_hit.t).abs() < 1e-12);
+
+        // A singular first placement doesn't leave the next one without a box
+        let flat = Instance::new(
+            first.mesh().clone(),
+            Transform::scale(Vec3::new(0.0, 0.0, 0.0)),
+        );
+        assert!(flat.place(transform).hit(down_the_z_axis()).t > 0.0);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{
+    config::{Background, IdKind},
+    instance::Aabb,
+    material::Material,
+    sampler::Sampler,
+    vec3::{barycentric, dot, sample_cosine_hemisphere, unit_vector},
+    Hit, Ray, Vec3, World,
+};
+
+/// What an integrator can see of the scene
+pub struct SceneView<'a> {
+    world: &'a World,
+    background: Background,
+    bounds: Option<Aabb>,
+}
+
+impl<'a> SceneView<'a> {
+    pub(crate) fn new(world: &'a World, background: Background, bounds: Option<Aabb>) -> Self {
+        Self {
+            world,
+            background,
+            bounds,
+        }
+    }
+
+    /// Find the closest triangle a ray hits
+    /// # Arguments
+    /// * 'ray' - The ray to cast
+    /// # Returns
+    /// * The hit, or None if the ray doesn't hit anything
+    pub fn hit(&self, ray: Ray) -> Option<Hit> {
+        let hit = self.world.hit(ray);
+        (hit.t > 0.0).then_some(hit)
+    }
+
+    /// Calculate the color seen by a ray which doesn't hit anything
+    /// # Arguments
+    /// * 'direction' - Direction of the ray
+    pub fn background(&self, direction: Vec3) -> Vec3 {
+        self.background.color(direction)
+    }
+
+    /// Smallest axis aligned box around everything in the scene
+    /// # Returns
+    /// * Its min and max corners, or None if the scene is empty
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        self.bounds.map(|b| (b.min, b.max))
+    }
+}
+
+/// Calculates the color of each sample from the ray leaving the camera
+/// # Notes
+/// * The ray tracer picks the samples and their rays, and averages the results with the reconstruction filter
+/// * Set one with RayTracerConfig::integrator, otherwise the drawing mode picks one of the built in integrators
+pub trait Integrator: Send + Sync {
+    /// Calculate the light arriving at the camera along a ray
+    /// # Arguments
+    /// * 'ray' - Ray leaving the camera
+    /// * 'scene' - What the ray can hit
+    /// * 'sampler' - Random values of the sample, e.g. sampler.bounce() for each bounce
+    /// # Returns
+    /// * Vec3 which contains r,g,b values in the x,y,z position of the vector
+    fn radiance(&self, ray: Ray, scene: &SceneView, sampler: &mut dyn Sampler) -> Vec3;
+
+    /// Whether the result is light, which is gamma corrected, or a color from 0-1 which is shown as it is, like the debug views
+    fn gamma_correct(&self) -> bool {
+        true
+    }
+}
+
+/// Shows the color of whatever the ray hits, used by DrawingMode::Colors
+pub struct ColorsIntegrator;
+
+impl Integrator for ColorsIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, _sampler: &mut dyn Sampler) -> Vec3 {
+        match scene.hit(ray) {
+            // Simply return the color of what the ray hit
+            Some(hit) => hit.material.get_albedo() * hit.vertex_color(),
+            None => scene.background(ray.direction),
+        }
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Shows the normals of whatever the ray hits as colors, used by DrawingMode::Normals
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, _sampler: &mut dyn Sampler) -> Vec3 {
+        match scene.hit(ray) {
+            Some(hit) => {
+                // Calculate color based on the normal
+                let n = hit.normal();
+                Vec3::new(n.x + 1.0, n.y + 1.0, n.z + 1.0) * 0.5
+            }
+            None => scene.background(ray.direction),
+        }
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Path tracer which follows the bounces of each ray and adds up the light it finds, used by DrawingMode::Samples
+/// # Notes
+/// * After roulette_depth bounces paths are randomly stopped, more likely the less light they carry, and the survivors are scaled up to make up for it
+/// * Paths are cut off at max_depth bounces, which only loses the little light of very long paths
+/// * A ray the material absorbs ends the path without seeing the background
+pub struct PathIntegrator {
+    /// Most bounces a path can have
+    pub max_depth: u32,
+    /// Bounces after which paths may be stopped by Russian roulette
+    pub roulette_depth: u32,
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, sampler: &mut dyn Sampler) -> Vec3 {
+        let mut radiance = Vec3::new(0.0, 0.0, 0.0);
+
+        // Fraction of the light at the current bounce which reaches the camera
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut ray = ray;
+
+        for depth in 0..self.max_depth {
+            let Some(hit) = scene.hit(ray) else {
+                // Nothing was hit, so we see the background, by default the blueish gradient
+                return radiance + throughput * scene.background(ray.direction);
+            };
+
+            // Light given off by the object itself, black unless it's emissive
+            radiance = radiance + throughput * hit.material.emitted(&hit);
+
+            // Will store the new ray, i.e. we bounce off the object and have a new ray based on the bounce
+            let mut scattered = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+
+            // Store the current color of whatever the ray bounces off
+            let mut attenuation = Vec3::new(0.0, 0.0, 0.0);
+
+            // Make sure we correctly scatter based on the objects material
+            let bounce = sampler.bounce();
+            let roulette = sampler.roulette();
+            if !hit
+                .material
+                .scatter(ray, hit.clone(), &mut attenuation, &mut scattered, bounce)
+            {
+                return radiance;
+            }
+            throughput = throughput * attenuation;
+            ray = scattered;
+
+            // Russian roulette, paths survive with the probability of their brightest channel
+            if depth + 1 >= self.roulette_depth {
+                let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+                if roulette >= survival {
+                    return radiance;
+                }
+                throughput = throughput * (1.0 / survival);
+            }
+        }
+
+        radiance
+    }
+}
+
+/// Ambient occlusion, how much of the hemisphere around each point is open, as a gray value from 0-1
+/// # Notes
+/// * Rays are spread around the interpolated normal with the cosine of their angle, so the open fraction weights them correctly
+/// * Rays which don't hit anything are white, the result isn't gamma corrected so it can be used as it is, e.g. as an AOV
+/// * Used by DrawingMode::AmbientOcclusion
+#[derive(Copy, Clone, Debug)]
+pub struct AoIntegrator {
+    /// Rays cast from each point a camera ray hits
+    pub samples: u32,
+    /// Distance within which a hit counts as occluding
+    pub max_distance: f64,
+}
+
+impl Default for AoIntegrator {
+    /// 16 rays per point with a distance of 1
+    fn default() -> Self {
+        Self {
+            samples: 16,
+            max_distance: 1.0,
+        }
+    }
+}
+
+impl Integrator for AoIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, sampler: &mut dyn Sampler) -> Vec3 {
+        let Some(hit) = scene.hit(ray) else {
+            return Vec3::new(1.0, 1.0, 1.0);
+        };
+
+        // Occlude the side the camera sees, also for the back of a surface
+        let mut n = hit.normal();
+        if dot(n, ray.direction) > 0.0 {
+            n = n * -1.0;
+        }
+
+        // Start slightly off the surface so the rays don't hit it again
+        let origin = hit.at + n * 1e-6;
+        let samples = self.samples.max(1);
+        let open = (0..samples)
+            .filter(|_| {
+                let direction = sample_cosine_hemisphere(sampler.get_2d(), n);
+                scene
+                    .hit(Ray::new(origin, direction))
+                    .is_none_or(|occluder| occluder.t > self.max_distance)
+            })
+            .count();
+
+        let ao = open as f64 / samples as f64;
+        Vec3::new(ao, ao, ao)
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Linear depth along the direction the camera looks in, from black at near to white at far, used by DrawingMode::Depth
+/// # Notes
+/// * Without a near or far distance the closest or farthest corner of the box around the scene is used, so the whole scene fits
+/// * Rays which don't hit anything are white, like the far distance
+pub struct DepthIntegrator {
+    /// Depth which is black, None to fit it to the scene
+    pub near: Option<f64>,
+    /// Depth which is white, None to fit it to the scene
+    pub far: Option<f64>,
+    /// Direction the camera looks in, the depth is measured along it
+    pub forward: Vec3,
+}
+
+impl Integrator for DepthIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, _sampler: &mut dyn Sampler) -> Vec3 {
+        let Some(hit) = scene.hit(ray) else {
+            return Vec3::new(1.0, 1.0, 1.0);
+        };
+        let forward = unit_vector(self.forward);
+        let depth = dot(hit.at - ray.origin, forward);
+
+        // Depths of the scene's corners, the hit is always within them
+        let (closest, farthest) = scene.bounds.map_or((depth, depth), |bounds| {
+            bounds
+                .corners()
+                .iter()
+                .map(|&corner| dot(corner - ray.origin, forward))
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), d| {
+                    (min.min(d), max.max(d))
+                })
+        });
+        let near = self.near.unwrap_or(closest.max(0.0));
+        let far = self.far.unwrap_or(farthest);
+
+        let value = if far > near {
+            ((depth - near) / (far - near)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        Vec3::new(value, value, value)
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Shows the world position of whatever the ray hits as colors, used by DrawingMode::Position
+/// # Notes
+/// * x,y,z go from 0 to 1 across the box around the scene, an axis the scene is flat in is 0.5
+/// * Rays which don't hit anything are black
+pub struct PositionIntegrator;
+
+impl Integrator for PositionIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, _sampler: &mut dyn Sampler) -> Vec3 {
+        let (Some(hit), Some((min, max))) = (scene.hit(ray), scene.bounds()) else {
+            return Vec3::new(0.0, 0.0, 0.0);
+        };
+        let normalize = |p: f64, min: f64, max: f64| {
+            if max > min {
+                ((p - min) / (max - min)).clamp(0.0, 1.0)
+            } else {
+                0.5
+            }
+        };
+        Vec3::new(
+            normalize(hit.at.x, min.x, max.x),
+            normalize(hit.at.y, min.y, max.y),
+            normalize(hit.at.z, min.z, max.z),
+        )
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Shows the texture coordinates of whatever the ray hits as red and green, used by DrawingMode::Uv
+/// # Notes
+/// * Only the fraction of the u,v is shown, so repeating textures show each repeat
+/// * Triangles without uvs are magenta and rays which don't hit anything are black
+pub struct UvIntegrator;
+
+impl Integrator for UvIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, _sampler: &mut dyn Sampler) -> Vec3 {
+        match scene.hit(ray).map(|hit| hit.uv()) {
+            Some(Some([u, v])) => Vec3::new(u - u.floor(), v - v.floor(), 0.0),
+            Some(None) => Vec3::new(1.0, 0.0, 1.0),
+            None => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Shows the barycentric coordinates of the hit within its triangle as colors, used by DrawingMode::Barycentric
+/// # Notes
+/// * Each corner of a triangle is red, green or blue, which shows the size and winding of the triangles
+/// * Rays which don't hit anything are black
+pub struct BarycentricIntegrator;
+
+impl Integrator for BarycentricIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, _sampler: &mut dyn Sampler) -> Vec3 {
+        match scene.hit(ray) {
+            Some(hit) => barycentric(hit),
+            None => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Shows which side of the triangles the camera sees, used by DrawingMode::FaceOrientation
+/// # Notes
+/// * The front, which the triangle's normal points out of, is blue and the back is red
+/// * Rays which don't hit anything are black
+pub struct FaceOrientationIntegrator;
+
+impl Integrator for FaceOrientationIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, _sampler: &mut dyn Sampler) -> Vec3 {
+        match scene.hit(ray) {
+            Some(hit) if dot(hit.triangle.normal, ray.direction) < 0.0 => Vec3::new(0.2, 0.4, 1.0),
+            Some(_) => Vec3::new(1.0, 0.2, 0.2),
+            None => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Shows the object or material ID of whatever the ray hits as a false color, used by DrawingMode::Ids
+/// # Notes
+/// * Consecutive IDs get hues far apart, so neighbouring objects are easy to tell apart
+/// * Rays which don't hit anything are black, write an ID buffer with RayTracer::render_ids for the exact IDs
+pub struct IdIntegrator {
+    /// Which ID to show
+    pub kind: IdKind,
+}
+
+impl Integrator for IdIntegrator {
+    fn radiance(&self, ray: Ray, scene: &SceneView, _sampler: &mut dyn Sampler) -> Vec3 {
+        match scene.hit(ray).and_then(|hit| hit.id(self.kind)) {
+            Some(id) => id_color(id),
+            None => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn gamma_correct(&self) -> bool {
+        false
+    }
+}
+
+/// Pick a bright color for an ID
+/// # Notes
+/// * The hue steps by the golden ratio, which spreads any number of IDs evenly around the color wheel
+fn id_color(id: u32) -> Vec3 {
+    let hue = (id as f64 * 0.618_033_988_749_895).fract() * 6.0;
+    let (saturation, value) = (0.7, 0.95);
+
+    // HSV to RGB, with the hue split into 6 sectors
+    let f = hue.fract();
+    let p = value * (1.0 - saturation);
+    let q = value * (1.0 - saturation * f);
+    let t = value * (1.0 - saturation * (1.0 - f));
+    match hue as u32 {
+        0 => Vec3::new(value, t, p),
+        1 => Vec3::new(q, value, p),
+        2 => Vec3::new(p, value, t),
+        3 => Vec3::new(p, q, value),
+        4 => Vec3::new(t, p, value),
+        _ => Vec3::new(value, p, q),
+    }
+}
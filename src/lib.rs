@@ -1,26 +1,72 @@
 mod camera;
 mod config;
 mod error;
+mod export;
+mod gltf;
+mod graph;
 mod hit;
+mod image;
+mod instance;
+mod integrator;
+mod light;
 mod material;
 mod mesh;
+mod obj;
+mod ply;
+mod primitive;
+mod progress;
 mod ray;
+mod sampler;
+mod scene;
+mod stl;
+#[cfg(test)]
+mod test_util;
+mod texture;
+mod tile;
 mod tracer;
+mod transform;
 mod triangle;
 mod vec3;
 mod world;
 
-pub use config::{DrawingMode, RayTracerConfig};
+pub use camera::CameraSettings;
+pub use config::{
+    AdaptiveSampling, Background, DrawingMode, Filter, FilterKind, IdKind, RayTracerConfig, Region,
+    TileOrder,
+};
 pub use error::Error;
-pub use material::{Diffuse, Material, MaterialEnum, Metal};
-pub use mesh::load_mesh;
+pub use export::{write_obj, write_ply};
+pub use gltf::{load_gltf, GltfCamera, GltfLight, GltfMesh, GltfScene};
+pub use graph::Node;
+pub use hit::Hit;
+pub use image::{IdBuffer, Image, ImageFormat, ImageWriter};
+pub use instance::Instance;
+pub use integrator::{
+    AoIntegrator, BarycentricIntegrator, ColorsIntegrator, DepthIntegrator,
+    FaceOrientationIntegrator, IdIntegrator, Integrator, NormalsIntegrator, PathIntegrator,
+    PositionIntegrator, SceneView, UvIntegrator,
+};
+pub use light::{Light, LightKind};
+pub use material::{Diffuse, Material, MaterialEnum, Metal, Pbr};
+pub use mesh::{Face, Mesh, MeshError, NormalWeighting};
+pub use obj::{load_mesh, load_obj_graph, load_obj_scene, ObjObject};
+pub use ply::load_ply;
+pub use primitive::{cuboid, quad, uv_sphere};
+pub use progress::{Budget, CancelToken, Pass, Progress};
+pub use ray::Ray;
+pub use sampler::{
+    BounceSample, HaltonSampler, IndependentSampler, Sampler, SamplerKind, SobolSampler,
+    StratifiedSampler,
+};
+pub use scene::{load_scene, Scene, SceneError, SceneStats};
+pub use stl::load_stl;
+pub use texture::{Texture, TextureError};
+pub use tile::{Tile, TileSink};
 pub use tracer::RayTracer;
-pub use vec3::Vec3;
+pub use transform::{Mat4, Transform};
+pub use triangle::Triangle;
+pub use vec3::{cross, dot, unit_vector, Vec3};
 
 use camera::Camera;
 use error::Result;
-use hit::Hit;
-use mesh::Mesh;
-use ray::Ray;
-use triangle::Triangle;
 use world::World;
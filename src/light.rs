@@ -0,0 +1,49 @@
+use crate::{vec3::unit_vector, Transform, Vec3};
+
+/// Type of a punctual light
+/// * 'Directional' - Infinitely far away, shining along its direction
+/// * 'Point' - Shining in all directions from its position
+/// * 'Spot' - Shining from its position in a cone around its direction, angles in radians
+#[derive(Copy, Clone, Debug)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_cone_angle: f64,
+        outer_cone_angle: f64,
+    },
+}
+
+/// A punctual light, a point in space rather than a mesh
+/// # Notes
+/// * The ray tracer only lights scenes with the sky and emissive materials, lights are kept so they can be replaced with emissive meshes
+#[derive(Clone, Debug)]
+pub struct Light {
+    /// Type of light
+    pub kind: LightKind,
+    /// Linear r,g,b color in the x,y,z positions
+    pub color: Vec3,
+    /// Brightness, in candela for point and spot lights and lux for directional lights
+    pub intensity: f64,
+    /// Distance after which the light has no effect, None for infinite
+    pub range: Option<f64>,
+    /// Position, relative to the node when placed in a scene graph
+    pub position: Vec3,
+    /// Direction the light shines in, relative to the node when placed in a scene graph
+    pub direction: Vec3,
+}
+
+impl Light {
+    /// Move the light
+    /// # Arguments
+    /// * 'transform' - Transform applied to the light's position and direction
+    /// # Returns
+    /// * The moved light with a unit direction
+    pub fn transformed(&self, transform: &Transform) -> Light {
+        Light {
+            position: transform.transform_point(self.position),
+            direction: unit_vector(transform.transform_vector(self.direction)),
+            ..self.clone()
+        }
+    }
+}
@@ -1,38 +1,527 @@
-use std::fs::File;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
 
-use anyhow::Context;
-use rust_raytracer::{load_mesh, Diffuse, DrawingMode, MaterialEnum, Metal, RayTracerConfig, Vec3};
+use anyhow::{bail, Context};
+use clap::{Parser, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
+use rust_raytracer::{
+    load_scene, AdaptiveSampling, AoIntegrator, Budget, CancelToken, DrawingMode, Filter,
+    FilterKind, IdKind, ImageFormat, ImageWriter, Region, SamplerKind, TileOrder,
+};
+
+/// Render a scene file to an image
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Scene file to render
+    #[arg(default_value = "scenes/default.toml")]
+    scene: String,
+
+    /// Image width in pixels, overriding the scene's
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    width: Option<u32>,
+
+    /// Image height in pixels, overriding the scene's
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    height: Option<u32>,
+
+    /// Samples per pixel, keeps the ao mode of a scene and otherwise switches to the samples mode, with --progressive the total to stop at
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    samples: Option<u32>,
+
+    /// Most samples per pixel, enables adaptive sampling which starts with --samples and stops once a pixel is converged
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "progressive")]
+    max_samples: Option<u32>,
+
+    /// Noise at which a pixel counts as converged, enables adaptive sampling, with --progressive the average noise to stop at
+    #[arg(long, value_parser = parse_threshold)]
+    noise_threshold: Option<f64>,
+
+    /// Number of bounces a ray can have
+    #[arg(long)]
+    max_depth: Option<u32>,
+
+    /// Bounces after which paths may be stopped by Russian roulette
+    #[arg(long)]
+    roulette_depth: Option<u32>,
+
+    /// Drawing mode, the samples and ao modes keep the scene's samples per pixel if it is already in that mode, or take 3 unless --samples is given, the others are debug views
+    #[arg(long, value_enum)]
+    mode: Option<Mode>,
+
+    /// Depth which is black in the depth mode, the closest point of the scene by default
+    #[arg(long, value_parser = parse_near)]
+    depth_near: Option<f64>,
+
+    /// Depth which is white in the depth mode, the farthest point of the scene by default
+    #[arg(long, value_parser = parse_distance)]
+    depth_far: Option<f64>,
+
+    /// Image file to write, the format is picked from the extension (.ppm or .png)
+    #[arg(short, long, default_value = "output.ppm")]
+    output: String,
+
+    /// Rays cast from each point for ambient occlusion
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    ao_samples: Option<u32>,
+
+    /// Distance within which a hit counts as occluding for ambient occlusion
+    #[arg(long, value_parser = parse_distance)]
+    ao_distance: Option<f64>,
+
+    /// Also write an ambient occlusion image of the same pixels, after the main image
+    #[arg(long, conflicts_with = "progressive")]
+    ao_output: Option<String>,
+
+    /// Also write the object ID of each pixel, as a 16 bit .png or a .pgm with 0 where nothing was hit
+    #[arg(long)]
+    object_ids: Option<String>,
+
+    /// Also write the material ID of each pixel, as a 16 bit .png or a .pgm with 0 where nothing was hit
+    #[arg(long)]
+    material_ids: Option<String>,
+
+    /// Number of threads to render with, all cores by default
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// How the random values of the samples are generated, overriding the scene's
+    #[arg(long, value_enum)]
+    sampler: Option<SamplerChoice>,
+
+    /// Reconstruction filter which weights the samples around each pixel, overriding the scene's
+    #[arg(long, value_enum)]
+    filter: Option<FilterChoice>,
+
+    /// Radius of the filter in pixels, at least 0.5, each filter has its own default
+    #[arg(long, value_parser = parse_radius, requires = "filter")]
+    filter_radius: Option<f64>,
+
+    /// Seed for the random sampling, renders with the same seed are identical
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Width and height of the tiles the image is rendered in
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    tile_size: u32,
+
+    /// Order the tiles are rendered in
+    #[arg(long, value_enum, default_value = "scanline")]
+    tile_order: Order,
+
+    /// Only render part of the image, as x,y,width,height in pixels from the top left corner
+    #[arg(long, value_parser = parse_region)]
+    crop: Option<Region>,
+
+    /// Refine the whole image in passes of 1, 2, 4, ... samples per pixel, rewriting the output after each one
+    #[arg(long)]
+    progressive: bool,
+
+    /// Seconds a progressive render may take
+    #[arg(long, value_parser = parse_seconds, requires = "progressive")]
+    time_limit: Option<Duration>,
+
+    /// Load and check the scene, print what's in it and exit without rendering
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Mode {
+    Colors,
+    Normals,
+    Samples,
+    Ao,
+    Depth,
+    Position,
+    Uv,
+    Barycentric,
+    Faces,
+    Objects,
+    Materials,
+}
+
+impl Mode {
+    /// Whether the mode takes --samples jittered samples per pixel, the others take one in the corner
+    fn is_sampled(self) -> bool {
+        matches!(self, Mode::Samples | Mode::Ao)
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum SamplerChoice {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum FilterChoice {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Order {
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+/// Parse a region written as x,y,width,height
+fn parse_region(s: &str) -> Result<Region, String> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|e| e.to_string())?;
+    match values[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        }),
+        [_, _, _, _] => Err("width and height must be greater than 0".to_string()),
+        _ => Err("expected x,y,width,height".to_string()),
+    }
+}
+
+/// Parse a number of seconds, which must be greater than 0
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    match s.trim().parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(Duration::from_secs_f64(seconds)),
+        Ok(_) => Err("must be greater than 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a near distance, which can't be negative
+fn parse_near(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(near) if near >= 0.0 => Ok(near),
+        Ok(_) => Err("must be at least 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a distance, which must be greater than 0
+fn parse_distance(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(distance) if distance > 0.0 => Ok(distance),
+        Ok(_) => Err("must be greater than 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a filter radius, which must be at least 0.5
+fn parse_radius(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(radius) if radius >= 0.5 && radius.is_finite() => Ok(radius),
+        Ok(_) => Err("must be at least 0.5".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a noise threshold, which must be greater than 0
+fn parse_threshold(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(threshold) if threshold > 0.0 && threshold.is_finite() => Ok(threshold),
+        Ok(_) => Err("must be greater than 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
 
 fn main() -> anyhow::Result<()> {
-    let mut ray_tracer = RayTracerConfig::default()
-        .width(1000)
-        .height(1000)
-        .mode(DrawingMode::Samples(5))
-        .build();
-
-    // Default scene
-    // Floor object
-    let mut floor = load_mesh("models/plane.obj", false).context("Failed to load plane mesh")?;
-    floor.scale(4.0);
-    floor.rotate(Vec3::new(0.0, 0.0, 0.0));
-    floor.translate(Vec3::new(0.0, -1.4, -10.0));
-    floor.material = MaterialEnum::Metal(Metal::new(Vec3::new(0.89, 0.4, 0.4), 0.0));
-
-    // Cube object
-    let mut cube = load_mesh("models/cube.obj", false).context("Failed to load cube mesh")?;
-    cube.scale(1.0);
-    cube.rotate(Vec3::new(0.0, 10.0, 0.0));
-    cube.translate(Vec3::new(0.0, -0.4, -12.0));
-    cube.material = MaterialEnum::Diffuse(Diffuse::new(Vec3::new(0.8, 0.8, 0.4)));
-
-    // Add objects to the world
-    ray_tracer.add_mesh(floor);
-    ray_tracer.add_mesh(cube);
-
-    let mut output_file = File::create("output.ppm").context("Failed to create PPM file")?;
-    ray_tracer
-        .run_parallel(&mut output_file)
-        .context("Failed to run ray tracer")?;
+    let args = Args::parse();
+
+    // Check the outputs before spending time on loading and rendering
+    let format = ImageFormat::from_path(&args.output).with_context(|| {
+        format!(
+            "Unknown image format for {}, expected a .ppm or .png file",
+            args.output
+        )
+    })?;
+    if let Some(ao_output) = &args.ao_output {
+        ImageFormat::from_path(ao_output).with_context(|| {
+            format!(
+                "Unknown image format for {}, expected a .ppm or .png file",
+                ao_output
+            )
+        })?;
+    }
+    let id_outputs = [
+        (IdKind::Object, &args.object_ids),
+        (IdKind::Material, &args.material_ids),
+    ];
+    for (_, path) in id_outputs {
+        if let Some(path) = path {
+            let extension = Path::new(path).extension().map(|e| e.to_ascii_lowercase());
+            if !matches!(extension, Some(e) if e == "png" || e == "pgm") {
+                bail!(
+                    "Unknown ID buffer format for {}, expected a .pgm or .png file",
+                    path
+                );
+            }
+        }
+    }
+
+    let mut scene = load_scene(&args.scene).context("Failed to load scene")?;
+
+    // Command line options override the scene's render settings
+    let mut config = scene.config;
+    if let Some(width) = args.width {
+        config = config.width(width);
+    }
+    if let Some(height) = args.height {
+        config = config.height(height);
+    }
+    if let Some(max_depth) = args.max_depth {
+        config = config.max_depth(max_depth);
+    }
+    if let Some(roulette_depth) = args.roulette_depth {
+        config = config.roulette_depth(roulette_depth);
+    }
+    match (args.mode, args.samples) {
+        (Some(mode), Some(_)) if !mode.is_sampled() => {
+            bail!("--samples can only be used with the samples and ao modes")
+        }
+        (Some(Mode::Colors), None) => config = config.mode(DrawingMode::Colors),
+        (Some(Mode::Normals), None) => config = config.mode(DrawingMode::Normals),
+        (Some(Mode::Samples), None) => {
+            // Keep the number of samples of a scene which is already in the samples mode
+            if !matches!(config.drawing_mode(), DrawingMode::Samples(_)) {
+                config = config.mode(DrawingMode::Samples(3))
+            }
+        }
+        (Some(Mode::Ao), samples) => {
+            let samples = match (samples, config.drawing_mode()) {
+                (Some(samples), _) => samples,
+                (None, DrawingMode::AmbientOcclusion(samples)) => samples,
+                (None, _) => 3,
+            };
+            config = config.mode(DrawingMode::AmbientOcclusion(samples))
+        }
+        (Some(Mode::Depth), None) => {
+            // Keep the near and far depths of a scene which is already in the depth mode
+            if !matches!(config.drawing_mode(), DrawingMode::Depth { .. }) {
+                config = config.mode(DrawingMode::Depth {
+                    near: None,
+                    far: None,
+                })
+            }
+        }
+        (Some(Mode::Position), None) => config = config.mode(DrawingMode::Position),
+        (Some(Mode::Uv), None) => config = config.mode(DrawingMode::Uv),
+        (Some(Mode::Barycentric), None) => config = config.mode(DrawingMode::Barycentric),
+        (Some(Mode::Faces), None) => config = config.mode(DrawingMode::FaceOrientation),
+        (Some(Mode::Objects), None) => config = config.mode(DrawingMode::Ids(IdKind::Object)),
+        (Some(Mode::Materials), None) => config = config.mode(DrawingMode::Ids(IdKind::Material)),
+        (Some(_), Some(samples)) => config = config.mode(DrawingMode::Samples(samples)),
+        (None, Some(samples)) => {
+            // Keep the scene's sampled mode, only changing its number of samples
+            let mode = match config.drawing_mode() {
+                DrawingMode::AmbientOcclusion(_) => DrawingMode::AmbientOcclusion(samples),
+                _ => DrawingMode::Samples(samples),
+            };
+            config = config.mode(mode);
+        }
+        (None, None) => {}
+    }
+    if args.depth_near.is_some() || args.depth_far.is_some() {
+        let DrawingMode::Depth { near, far } = config.drawing_mode() else {
+            bail!("--depth-near and --depth-far can only be used with the depth mode")
+        };
+        let (near, far) = (args.depth_near.or(near), args.depth_far.or(far));
+        if let (Some(near), Some(far)) = (near, far) {
+            if far <= near {
+                bail!("The far depth must be greater than the near depth")
+            }
+        }
+        config = config.mode(DrawingMode::Depth { near, far });
+    }
+    if !args.progressive && (args.max_samples.is_some() || args.noise_threshold.is_some()) {
+        if args.mode.is_some_and(|mode| !mode.is_sampled()) {
+            bail!("--max-samples and --noise-threshold can only be used with the samples and ao modes")
+        }
+        let default = AdaptiveSampling::default();
+        config = config.adaptive(AdaptiveSampling {
+            max_samples: args.max_samples.unwrap_or(default.max_samples),
+            noise_threshold: args.noise_threshold.unwrap_or(default.noise_threshold),
+        });
+    }
+    // Ambient occlusion options override the scene's one at a time
+    let scene_ao = config.ambient_occlusion_settings();
+    config = config.ambient_occlusion(AoIntegrator {
+        samples: args.ao_samples.unwrap_or(scene_ao.samples),
+        max_distance: args.ao_distance.unwrap_or(scene_ao.max_distance),
+    });
+    let ao = config.ambient_occlusion_settings();
+    config = config.seed(args.seed);
+    if let Some(sampler) = args.sampler {
+        config = config.sampler(match sampler {
+            SamplerChoice::Independent => SamplerKind::Independent,
+            SamplerChoice::Stratified => SamplerKind::Stratified,
+            SamplerChoice::Halton => SamplerKind::Halton,
+            SamplerChoice::Sobol => SamplerKind::Sobol,
+        });
+    }
+    if let Some(choice) = args.filter {
+        let mut filter = Filter::new(match choice {
+            FilterChoice::Box => FilterKind::Box,
+            FilterChoice::Tent => FilterKind::Tent,
+            FilterChoice::Gaussian => FilterKind::Gaussian,
+            FilterChoice::Mitchell => FilterKind::Mitchell,
+            FilterChoice::Lanczos => FilterKind::Lanczos,
+        });
+        if let Some(radius) = args.filter_radius {
+            filter.radius = radius;
+        }
+        config = config.filter(filter);
+    }
+    if let Some(crop) = args.crop {
+        config = config.region(crop);
+    }
+    config = config
+        .tile_size(args.tile_size)
+        .tile_order(match args.tile_order {
+            Order::Scanline => TileOrder::Scanline,
+            Order::Spiral => TileOrder::Spiral,
+            Order::Hilbert => TileOrder::Hilbert,
+        });
+
+    // Show a progress bar on the terminal, hidden when stderr isn't one
+    let bar = ProgressBar::new(0).with_style(
+        ProgressStyle::with_template(
+            "{spinner} [{elapsed_precise}] {wide_bar} {pos}/{len}, {msg} left",
+        )
+        .context("Invalid progress bar template")?,
+    );
+    let progress_bar = bar.clone();
+    config = config.on_progress(move |progress| {
+        progress_bar.set_length(progress.total as u64);
+        progress_bar.set_position(progress.completed as u64);
+        progress_bar.set_message(format!("{}s", progress.eta.as_secs()));
+    });
+
+    // Stop the render cleanly on Ctrl-C, the unfinished image is removed but a progressive render keeps its last pass
+    let cancel = CancelToken::new();
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || handler_cancel.cancel()).context("Failed to set Ctrl-C handler")?;
+    config = config.cancel_token(cancel);
+    scene.config = config;
+
+    let stats = scene.stats();
+    let mut ray_tracer = scene.build();
+    let (width, height) = ray_tracer.output_size();
+    if width == 0 || height == 0 {
+        bail!("The crop region is outside of the image");
+    }
+
+    if args.dry_run {
+        println!("Scene: {}", args.scene);
+        println!("Output: {} ({}x{} pixels)", args.output, width, height);
+        println!("Mode: {:?}", ray_tracer.mode());
+        println!(
+            "Nodes: {}, instances: {}, meshes: {}",
+            stats.nodes, stats.instances, stats.meshes
+        );
+        println!(
+            "Triangles: {}, vertices: {}",
+            stats.triangles, stats.vertices
+        );
+        return Ok(());
+    }
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .context("Failed to create thread pool")?;
+    }
+
+    // The ID buffers are quick, so they're written before the main image
+    for (kind, path) in id_outputs {
+        if let Some(path) = path {
+            let ids = ray_tracer
+                .render_ids(kind)
+                .context("Failed to render IDs")?;
+            ids.save(path).context("Failed to write ID buffer")?;
+        }
+    }
+
+    if args.progressive {
+        let budget = Budget {
+            time: args.time_limit,
+            samples: args.samples,
+            noise: args.noise_threshold,
+        };
+        if budget.time.is_none() && budget.samples.is_none() && budget.noise.is_none() {
+            eprintln!(
+                "No --samples, --noise-threshold or --time-limit given, refining until Ctrl-C"
+            );
+        }
+
+        // Each pass replaces the output, through a temporary file so it's never half written
+        let partial = format!("{}.partial", args.output);
+        let result = ray_tracer.render_progressive(budget, &mut |image, pass| {
+            let mut file = BufWriter::new(File::create(&partial)?);
+            image.write(&mut file, format)?;
+            file.flush()?;
+            drop(file);
+            std::fs::rename(&partial, &args.output)?;
+            bar.suspend(|| {
+                eprintln!(
+                    "Pass {}: {} samples per pixel, noise {:.4}, {:.1}s",
+                    pass.index + 1,
+                    pass.samples,
+                    pass.noise,
+                    pass.elapsed.as_secs_f64()
+                )
+            });
+            Ok(())
+        });
+        bar.finish_and_clear();
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e).context("Failed to render");
+        }
+        return Ok(());
+    }
+
+    // Tiles are written to the file as they're finished
+    let mut file =
+        BufWriter::new(File::create(&args.output).context("Failed to create output file")?);
+    let result = ImageWriter::new(&mut file, width, height, format).and_then(|mut writer| {
+        ray_tracer.render_tiles(&mut writer)?;
+        writer.finish()
+    });
+    bar.finish_and_clear();
+    if let Err(e) = result {
+        drop(file);
+        let _ = std::fs::remove_file(&args.output);
+        return Err(e).context("Failed to render");
+    }
+    file.flush().context("Failed to write image")?;
+
+    // The ambient occlusion AOV shares the scene, camera and samples of the main image
+    if let Some(ao_output) = &args.ao_output {
+        ray_tracer.set_integrator(ao);
+        let image = ray_tracer
+            .render()
+            .context("Failed to render ambient occlusion")?;
+        image
+            .save(ao_output)
+            .context("Failed to write ambient occlusion image")?;
+    }
 
     Ok(())
 }
@@ -1,6 +1,11 @@
+use std::sync::Arc;
+
 use crate::{
-    vec3::{barycentric, dot, random_in_unit_sphere, random_unit_vector, reflect, unit_vector},
-    Hit, Ray, Vec3,
+    sampler::BounceSample,
+    vec3::{
+        barycentric, cross, dot, reflect, sample_in_unit_sphere, sample_unit_vector, unit_vector,
+    },
+    Hit, Ray, Texture, Vec3,
 };
 
 /// Store all the different types of materials
@@ -8,12 +13,14 @@ use crate::{
 pub enum MaterialEnum {
     Diffuse(Diffuse),
     Metal(Metal),
+    Pbr(Pbr),
 }
 
 /// Contains functions every material needs to be able to perform
 /// # Functions
 /// * 'scatter' - Tells the program how the ray should scatter based on the material
 /// * 'get_albedo' - Return the objects albedo color
+/// * 'emitted' - Return the light the object gives off
 pub trait Material {
     /// Determine how the ray will bounce off the object based on its material
     /// # Arguments
@@ -21,30 +28,63 @@ pub trait Material {
     /// * 'hit' - Information about what we hit
     /// * 'attenuation' - Will store the hit color
     /// * 'scattered' - Will store the new bounced ray
+    /// * 'u' - Random values for this bounce from the sampler, the only source of randomness so renders can be repeated
     /// # Returns
     /// * True or false based on the individual scatter implementation
-    fn scatter(&self, r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool;
+    fn scatter(
+        &self,
+        r: Ray,
+        hit: Hit,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+        u: BounceSample,
+    ) -> bool;
 
     /// Return the color of the object
     /// # Returns
     /// * Vec3 containing r,g,b values of the object in the x,y,z position
     fn get_albedo(&self) -> Vec3;
+
+    /// Return the light given off by the object
+    /// # Arguments
+    /// * 'hit' - Information about what we hit
+    /// # Returns
+    /// * Vec3 containing r,g,b values of the emitted light, black for most materials
+    fn emitted(&self, _hit: &Hit) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
 }
 
 impl Material for MaterialEnum {
     // Not sure if there's a better way to do this...
     // Need to call the various functions of the material based on which material it actually is
 
-    fn scatter(&self, r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(
+        &self,
+        r: Ray,
+        hit: Hit,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+        u: BounceSample,
+    ) -> bool {
         match self {
-            MaterialEnum::Diffuse(mat) => mat.scatter(r, hit, attenuation, scattered),
-            MaterialEnum::Metal(mat) => mat.scatter(r, hit, attenuation, scattered),
+            MaterialEnum::Diffuse(mat) => mat.scatter(r, hit, attenuation, scattered, u),
+            MaterialEnum::Metal(mat) => mat.scatter(r, hit, attenuation, scattered, u),
+            MaterialEnum::Pbr(mat) => mat.scatter(r, hit, attenuation, scattered, u),
         }
     }
     fn get_albedo(&self) -> Vec3 {
         match self {
             MaterialEnum::Diffuse(mat) => mat.get_albedo(),
             MaterialEnum::Metal(mat) => mat.get_albedo(),
+            MaterialEnum::Pbr(mat) => mat.get_albedo(),
+        }
+    }
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        match self {
+            MaterialEnum::Diffuse(mat) => mat.emitted(hit),
+            MaterialEnum::Metal(mat) => mat.emitted(hit),
+            MaterialEnum::Pbr(mat) => mat.emitted(hit),
         }
     }
 }
@@ -67,7 +107,14 @@ impl Diffuse {
 
 impl Material for Diffuse {
     // Scatter function for an object with a diffuse material
-    fn scatter(&self, _r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(
+        &self,
+        _r: Ray,
+        hit: Hit,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+        u: BounceSample,
+    ) -> bool {
         let mut scatter_direction: Vec3;
 
         // If the object is smooth shaded
@@ -81,7 +128,7 @@ impl Material for Diffuse {
                 hit.triangle.normals[0] * bary.x
                     + hit.triangle.normals[1] * bary.y
                     + hit.triangle.normals[2] * bary.z,
-            ) + random_unit_vector();
+            ) + sample_unit_vector(u.direction);
 
             if scatter_direction.near_zero() {
                 // If we're close to zero, just set as the normal
@@ -90,19 +137,19 @@ impl Material for Diffuse {
 
             // Set the current scattered ray based on the location the ray hit and the new direction
             *scattered = Ray::new(hit.at, scatter_direction);
-            *attenuation = self.albedo; // Current objects color
+            *attenuation = self.albedo * hit.vertex_color(); // Current objects color
         } else {
             // Not smooth shaded
 
             // Use the single normal and bounce
-            scatter_direction = hit.triangle.normal + random_unit_vector();
+            scatter_direction = hit.triangle.normal + sample_unit_vector(u.direction);
 
             if scatter_direction.near_zero() {
                 scatter_direction = hit.triangle.normal;
             }
 
             *scattered = Ray::new(hit.at, scatter_direction);
-            *attenuation = self.albedo;
+            *attenuation = self.albedo * hit.vertex_color();
         }
         true
     }
@@ -134,7 +181,14 @@ impl Metal {
 
 impl Material for Metal {
     // Scatter for a metal material
-    fn scatter(&self, r: Ray, hit: Hit, attenuation: &mut Vec3, scattered: &mut Ray) -> bool {
+    fn scatter(
+        &self,
+        r: Ray,
+        hit: Hit,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+        u: BounceSample,
+    ) -> bool {
         // Will contain the reflected direction
         let reflected: Vec3;
 
@@ -151,12 +205,12 @@ impl Material for Metal {
                     hit.triangle.normals[0] * bary.x
                         + hit.triangle.normals[1] * bary.y
                         + hit.triangle.normals[2] * bary.z,
-                ) + (random_in_unit_sphere() * self.smoothness),
+                ) + (sample_in_unit_sphere(u.direction, u.radius) * self.smoothness),
             );
 
             // Set the new scattered direction based on the reflection
             *scattered = Ray::new(hit.at, reflected);
-            *attenuation = self.albedo;
+            *attenuation = self.albedo * hit.vertex_color();
 
             // Make sure the scattered direction is in a similar direction as the normals of each vertex
             dot(scattered.direction, hit.triangle.normals[0]) > 0.0
@@ -165,10 +219,11 @@ impl Material for Metal {
         } else {
             reflected = reflect(
                 unit_vector(r.direction),
-                hit.triangle.normal + (random_in_unit_sphere() * self.smoothness),
+                hit.triangle.normal
+                    + (sample_in_unit_sphere(u.direction, u.radius) * self.smoothness),
             );
             *scattered = Ray::new(hit.at, reflected);
-            *attenuation = self.albedo;
+            *attenuation = self.albedo * hit.vertex_color();
 
             // Make sure the direction is similar to the triangle's normal
             dot(scattered.direction, hit.triangle.normal) > 0.0
@@ -179,3 +234,169 @@ impl Material for Metal {
         self.albedo
     }
 }
+
+/// Metallic-roughness material, as used by glTF
+/// # Notes
+/// * Each texture is multiplied with its factor and looked up with the triangle's uvs
+/// * Each bounce either reflects like metal or scatters like diffuse, chosen randomly by metallic
+#[derive(Clone, Debug)]
+pub struct Pbr {
+    /// Base color, the albedo for diffuse bounces and the tint for metal reflections
+    pub base_color: Vec3,
+    /// Texture the base color is multiplied with
+    pub base_color_texture: Option<Arc<Texture>>,
+    /// How metallic the surface is (0.0 diffuse, 1.0 metal)
+    pub metallic: f64,
+    /// How rough metal reflections are (0.0 is mirror, 1.0 not smooth at all)
+    pub roughness: f64,
+    /// Texture with roughness in its green and metallic in its blue channel
+    pub metallic_roughness_texture: Option<Arc<Texture>>,
+    /// Tangent space normal map
+    pub normal_texture: Option<Arc<Texture>>,
+    /// Strength of the normal map's x and y components
+    pub normal_scale: f64,
+    /// Color of the light the surface gives off
+    pub emissive: Vec3,
+    /// Texture the emissive color is multiplied with
+    pub emissive_texture: Option<Arc<Texture>>,
+}
+
+impl Pbr {
+    /// Create a new untextured metallic-roughness material
+    /// # Arguments
+    /// * 'base_color' - Desired color
+    /// * 'metallic' - How metallic the surface is (0.0 diffuse, 1.0 metal)
+    /// * 'roughness' - How rough the surface is (0.0 is mirror, 1.0 not smooth at all)
+    pub fn new(base_color: Vec3, metallic: f64, roughness: f64) -> Self {
+        Self {
+            base_color,
+            base_color_texture: None,
+            metallic,
+            roughness,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            emissive: Vec3::new(0.0, 0.0, 0.0),
+            emissive_texture: None,
+        }
+    }
+
+    /// Calculate the texture coordinates at the hit position
+    fn uv(hit: &Hit, bary: Vec3) -> [f64; 2] {
+        match hit.triangle.uvs {
+            Some(uvs) => [
+                uvs[0][0] * bary.x + uvs[1][0] * bary.y + uvs[2][0] * bary.z,
+                uvs[0][1] * bary.x + uvs[1][1] * bary.y + uvs[2][1] * bary.z,
+            ],
+            None => [0.0, 0.0],
+        }
+    }
+
+    /// Calculate the shading normal at the hit position, including the normal map
+    fn shading_normal(&self, hit: &Hit, bary: Vec3, uv: [f64; 2]) -> Vec3 {
+        let n = if hit.triangle.smooth {
+            unit_vector(
+                hit.triangle.normals[0] * bary.x
+                    + hit.triangle.normals[1] * bary.y
+                    + hit.triangle.normals[2] * bary.z,
+            )
+        } else {
+            hit.triangle.normal
+        };
+
+        let (Some(texture), Some(uvs)) = (&self.normal_texture, hit.triangle.uvs) else {
+            return n;
+        };
+
+        // Tangent and bitangent follow the direction u and v increase across the triangle
+        let p = hit.triangle.points;
+        let (edge1, edge2) = (p[1] - p[0], p[2] - p[0]);
+        let (du1, dv1) = (uvs[1][0] - uvs[0][0], uvs[1][1] - uvs[0][1]);
+        let (du2, dv2) = (uvs[2][0] - uvs[0][0], uvs[2][1] - uvs[0][1]);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < 1e-12 {
+            return n;
+        }
+        let tangent = (edge1 * dv2 - edge2 * dv1) / det;
+        let bitangent = (edge2 * du1 - edge1 * du2) / det;
+
+        // Make the tangent frame orthonormal around the shading normal
+        let tangent = tangent - n * dot(n, tangent);
+        if tangent.near_zero() {
+            return n;
+        }
+        let tangent = unit_vector(tangent);
+        let handedness = if dot(cross(n, tangent), bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        let bitangent = cross(n, tangent) * handedness;
+
+        // Normal maps store the -1 to 1 range as 0 to 1
+        let m = texture.sample(uv);
+        let x = (m.x * 2.0 - 1.0) * self.normal_scale;
+        let y = (m.y * 2.0 - 1.0) * self.normal_scale;
+        let z = m.z * 2.0 - 1.0;
+        unit_vector(tangent * x + bitangent * y + n * z)
+    }
+}
+
+impl Material for Pbr {
+    fn scatter(
+        &self,
+        r: Ray,
+        hit: Hit,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+        u: BounceSample,
+    ) -> bool {
+        let bary = barycentric(hit.clone());
+        let uv = Pbr::uv(&hit, bary);
+        let n = self.shading_normal(&hit, bary, uv);
+
+        let mut base_color = self.base_color;
+        if let Some(texture) = &self.base_color_texture {
+            base_color = base_color * texture.sample(uv);
+        }
+        let (mut metallic, mut roughness) = (self.metallic, self.roughness);
+        if let Some(texture) = &self.metallic_roughness_texture {
+            let m = texture.sample(uv);
+            roughness *= m.y;
+            metallic *= m.z;
+        }
+
+        *attenuation = base_color * hit.vertex_color();
+        if u.choice < metallic {
+            // Reflect like metal, fuzzed by the roughness
+            let reflected = reflect(
+                unit_vector(r.direction),
+                n + (sample_in_unit_sphere(u.direction, u.radius) * roughness),
+            );
+            *scattered = Ray::new(hit.at, reflected);
+            dot(scattered.direction, n) > 0.0
+        } else {
+            // Scatter like diffuse
+            let mut scatter_direction = n + sample_unit_vector(u.direction);
+            if scatter_direction.near_zero() {
+                scatter_direction = n;
+            }
+            *scattered = Ray::new(hit.at, scatter_direction);
+            true
+        }
+    }
+
+    fn get_albedo(&self) -> Vec3 {
+        self.base_color
+    }
+
+    fn emitted(&self, hit: &Hit) -> Vec3 {
+        match &self.emissive_texture {
+            Some(texture) => {
+                let bary = barycentric(hit.clone());
+                self.emissive * texture.sample(Pbr::uv(hit, bary))
+            }
+            None => self.emissive,
+        }
+    }
+}
@@ -1,23 +1,74 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-};
+use std::{collections::HashMap, ops::Range};
 
 use thiserror::Error;
 
 use crate::{
     material::{Diffuse, MaterialEnum},
-    vec3::unit_vector,
+    transform::Transform,
+    triangle::{geometric_normal, intersect},
+    vec3::{cross, dot, unit_vector},
     Hit, Ray, Triangle, Vec3,
 };
 
 /// Mesh struct
+/// # Notes
+/// * Vertex data is stored once in shared arrays, faces index into them
+/// * Triangles are only materialized when they're needed, like for a hit
 #[derive(Clone, Debug)]
 pub struct Mesh {
-    /// All of the triangles in a mesh
-    pub triangles: Vec<Triangle>,
-    /// The mesh's material
+    /// Vertex positions shared by the faces
+    pub positions: Vec<Vec3>,
+    /// Unit normals shared by the faces, both flat and per vertex
+    pub normals: Vec<Vec3>,
+    /// Texture coordinates shared by the faces
+    pub uvs: Vec<[f64; 2]>,
+    /// Vertex colors shared by the faces
+    pub colors: Vec<Vec3>,
+    /// All of the faces in a mesh
+    pub faces: Vec<Face>,
+    /// The mesh's default material, used by faces without a material index
     pub material: MaterialEnum,
+    /// Material table indexed by each face's material index
+    pub materials: Vec<MaterialEnum>,
+}
+
+/// A triangle of a mesh, made of indices into the mesh's vertex arrays
+#[derive(Copy, Clone, Debug)]
+pub struct Face {
+    /// Indices of the 3 points in the positions
+    pub positions: [usize; 3],
+    /// Index of the flat shaded normal in the normals
+    pub normal: usize,
+    /// Whether or not the face is smoothly shaded
+    pub smooth: bool,
+    /// Indices of the 3 per vertex normals in the normals, only used if smooth
+    pub normals: [usize; 3],
+    /// Indices of the 3 texture coordinates in the uvs, if the face has any
+    pub uvs: Option<[usize; 3]>,
+    /// Indices of the 3 colors in the colors, if the face has any
+    pub colors: Option<[usize; 3]>,
+    /// Index into the owning mesh's material table, None uses the mesh's default material
+    pub material: Option<usize>,
+}
+
+impl Face {
+    /// Create a new flat shaded face
+    /// # Arguments
+    /// * 'positions' - Indices of the three points
+    /// * 'normal' - Index of the face normal
+    /// # Returns
+    /// * Face using the flat normal at every corner, no uvs or colors and no material index
+    pub fn new(positions: [usize; 3], normal: usize) -> Self {
+        Self {
+            positions,
+            normal,
+            smooth: false,
+            normals: [normal; 3],
+            uvs: None,
+            colors: None,
+            material: None,
+        }
+    }
 }
 
 impl Default for Mesh {
@@ -30,173 +81,291 @@ impl Default for Mesh {
 impl Mesh {
     /// Create a new empty mesh
     /// # Default Values
-    /// * 'triangles' - Empty Rust vec
+    /// * 'positions, normals, uvs, colors, faces' - Empty Rust vecs
     /// * 'material' - White diffuse material
+    /// * 'materials' - Empty Rust vec
     pub fn new() -> Mesh {
         Mesh {
-            triangles: Vec::new(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            faces: Vec::new(),
             material: MaterialEnum::Diffuse(Diffuse::new(Vec3::new(1.0, 1.0, 1.0))),
+            materials: Vec::new(),
         }
     }
 
     /// Create a mesh with an already established Vec of triangles
     /// # Arguments
     /// * 'trigs' - Rust vec of triangles
+    /// # Notes
+    /// * The triangles' vertices aren't shared, loaders build the vertex arrays directly instead
     pub fn new_mesh(trigs: Vec<Triangle>) -> Mesh {
-        Mesh {
-            triangles: trigs,
+        let mut mesh = Mesh {
             material: MaterialEnum::Diffuse(Diffuse::new(Vec3::new(0.5, 0.5, 0.5))),
+            ..Mesh::new()
+        };
+        for trig in trigs {
+            mesh.add(trig);
         }
+        mesh
     }
 
     /// Create add triangles to a mesh
     /// # Arguments
-    /// * 'trig' - Single triangle to add
+    /// * 'trig' - Single triangle to add, its vertices are appended to the vertex arrays
     pub fn add(&mut self, trig: Triangle) {
-        self.triangles.push(trig);
+        /// Append three values to a vertex array and return their indices
+        fn append<T: Copy>(array: &mut Vec<T>, values: [T; 3]) -> [usize; 3] {
+            let start = array.len();
+            array.extend(values);
+            [start, start + 1, start + 2]
+        }
+
+        let mut face = Face::new(append(&mut self.positions, trig.points), self.normals.len());
+        self.normals.push(trig.normal);
+        if trig.smooth {
+            face.smooth = true;
+            face.normals = append(&mut self.normals, trig.normals);
+        }
+        face.uvs = trig.uvs.map(|uvs| append(&mut self.uvs, uvs));
+        face.colors = trig.colors.map(|colors| append(&mut self.colors, colors));
+        face.material = trig.material;
+        self.faces.push(face);
+    }
+
+    /// Build the triangle of a face
+    /// # Arguments
+    /// * 'face' - Index of the face
+    /// # Returns
+    /// * Triangle with the face's vertex data copied in, per vertex normals are empty unless smooth
+    pub fn triangle(&self, face: usize) -> Triangle {
+        let face = &self.faces[face];
+        let mut trig = Triangle::new_empty();
+        trig.points = self.points(face);
+        trig.normal = self.normals[face.normal];
+        if face.smooth {
+            trig.smooth = true;
+            trig.normals = face.normals.map(|n| self.normals[n]);
+        }
+        trig.uvs = face.uvs.map(|uvs| uvs.map(|uv| self.uvs[uv]));
+        trig.colors = face.colors.map(|colors| colors.map(|c| self.colors[c]));
+        trig.material = face.material;
+        trig
+    }
+
+    /// Iterate over the triangles of every face, in order
+    pub fn triangles(&self) -> impl Iterator<Item = Triangle> + '_ {
+        (0..self.faces.len()).map(|face| self.triangle(face))
+    }
+
+    /// The positions of a face's three points
+    fn points(&self, face: &Face) -> [Vec3; 3] {
+        face.positions.map(|p| self.positions[p])
+    }
+
+    /// Add a material to the mesh's material table
+    /// # Arguments
+    /// * 'material' - Material to add
+    /// # Returns
+    /// * The index to store in a face's material field to use this material
+    pub fn add_material(&mut self, material: MaterialEnum) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    /// Assign a material from the material table to a range of faces
+    /// # Arguments
+    /// * 'faces' - Range of face indices to assign
+    /// * 'material' - Index into the material table, None falls back to the default material
+    pub fn set_material(&mut self, faces: Range<usize>, material: Option<usize>) {
+        for face in self.faces[faces].iter_mut() {
+            face.material = material;
+        }
+    }
+
+    /// Look up the material a triangle is drawn with
+    /// # Arguments
+    /// * 'trig' - Triangle belonging to this mesh
+    /// # Returns
+    /// * The material at the triangle's index, or the default material if it has none or it's out of range
+    pub fn material_for(&self, trig: &Triangle) -> &MaterialEnum {
+        trig.material
+            .and_then(|index| self.materials.get(index))
+            .unwrap_or(&self.material)
+    }
+
+    /// Find which of the mesh's materials a triangle is drawn with
+    /// # Arguments
+    /// * 'trig' - Triangle belonging to this mesh
+    /// # Returns
+    /// * 0 for the default material, or 1 plus the index into the material table, like material_for picks them
+    pub fn material_slot(&self, trig: &Triangle) -> usize {
+        match trig.material {
+            Some(index) if index < self.materials.len() => index + 1,
+            _ => 0,
+        }
+    }
+
+    /// Transform a mesh
+    /// # Arguments
+    /// * 'transform' - The transform to apply to every vertex
+    /// # Notes
+    /// * Normals are transformed by the inverse transpose, so they stay perpendicular under non-uniform scales
+    /// * Normals collapsed by a scale of 0 become the zero vector
+    /// * Mirroring transforms swap two corners of every face to keep the winding counter-clockwise
+    pub fn transform(&mut self, transform: &Transform) {
+        // Every shared vertex is only transformed once, no matter how many faces use it
+        for point in self.positions.iter_mut() {
+            *point = transform.transform_point(*point);
+        }
+        for normal in self.normals.iter_mut() {
+            *normal = transform.transform_normal(*normal);
+        }
+
+        if transform.is_mirroring() {
+            for face in self.faces.iter_mut() {
+                face.positions.swap(1, 2);
+                face.normals.swap(1, 2);
+                if let Some(uvs) = face.uvs.as_mut() {
+                    uvs.swap(1, 2);
+                }
+                if let Some(colors) = face.colors.as_mut() {
+                    colors.swap(1, 2);
+                }
+            }
+        }
     }
 
     /// Translate a mesh
     /// # Arguments
     /// * 'd' - Vec3 which contains the x,y,z directions to translate
     pub fn translate(&mut self, d: Vec3) {
-        // Loop over each triangle in the mesh and simply add x,y,z to the points to translate
-        for trig in self.triangles.iter_mut() {
-            for point in trig.points.iter_mut() {
-                *point = Vec3::new(point.x + d.x, point.y + d.y, point.z + d.z);
-            }
-        }
+        self.transform(&Transform::translate(d));
     }
 
     /// Scale a mesh
     /// # Arguments
     /// * 'c' - Amount to scale
     pub fn scale(&mut self, c: f64) {
-        // Loop over each triangle in the mesh and scale the points
-        for trig in self.triangles.iter_mut() {
-            for point in trig.points.iter_mut() {
-                *point = Vec3::new(point.x * c, point.y * c, point.z * c);
-            }
-        }
+        self.transform(&Transform::scale(Vec3::new(c, c, c)));
     }
 
     /// Rotate a mesh
     /// # Arguments
-    /// * 'r' - Vec3 in degrees NOT radians
+    /// * 'r' - Vec3 in degrees NOT radians, rotating on x, then y, then z
     pub fn rotate(&mut self, r: Vec3) {
-        // Must convert to radians
-        let theta_x = r.x.to_radians();
-        let theta_y = r.y.to_radians();
-        let theta_z = r.z.to_radians();
-
-        // Need to rotate each triangle in the mesh
-        for trig in self.triangles.iter_mut() {
-            // First, let's rotate the normals
-            // Rotate on x
-            trig.normal = Vec3::new(
-                trig.normal.x,
-                trig.normal.y * theta_x.cos() - trig.normal.z * theta_x.sin(),
-                trig.normal.y * theta_x.sin() + trig.normal.z * theta_x.cos(),
-            );
-
-            // If the triangle is smoothly shaded, it will have normals for each vertex
-            // Rotate each of the normals. If not smooth shaded, this doesn't do much
-            trig.normals[0] = Vec3::new(
-                trig.normals[0].x,
-                trig.normals[0].y * theta_x.cos() - trig.normals[0].z * theta_x.sin(),
-                trig.normals[0].y * theta_x.sin() + trig.normals[0].z * theta_x.cos(),
-            );
-            trig.normals[1] = Vec3::new(
-                trig.normals[1].x,
-                trig.normals[1].y * theta_x.cos() - trig.normals[1].z * theta_x.sin(),
-                trig.normals[1].y * theta_x.sin() + trig.normals[1].z * theta_x.cos(),
-            );
-            trig.normals[2] = Vec3::new(
-                trig.normals[2].x,
-                trig.normals[2].y * theta_x.cos() - trig.normals[2].z * theta_x.sin(),
-                trig.normals[2].y * theta_x.sin() + trig.normals[2].z * theta_x.cos(),
-            );
-
-            // EACH time we rotate, we must normalize
-            trig.normal = unit_vector(trig.normal);
-            trig.normals[0] = unit_vector(trig.normals[0]);
-            trig.normals[1] = unit_vector(trig.normals[1]);
-            trig.normals[2] = unit_vector(trig.normals[2]);
-
-            // Rotate on y
-            trig.normal = Vec3::new(
-                trig.normal.x * theta_y.cos() + trig.normal.z * theta_y.sin(),
-                trig.normal.y,
-                -trig.normal.x * theta_y.sin() + trig.normal.z * theta_y.cos(),
-            );
-            trig.normals[0] = Vec3::new(
-                trig.normals[0].x * theta_y.cos() + trig.normals[0].z * theta_y.sin(),
-                trig.normals[0].y,
-                -trig.normals[0].x * theta_y.sin() + trig.normals[0].z * theta_y.cos(),
-            );
-            trig.normals[1] = Vec3::new(
-                trig.normals[1].x * theta_y.cos() + trig.normals[1].z * theta_y.sin(),
-                trig.normals[1].y,
-                -trig.normals[1].x * theta_y.sin() + trig.normals[1].z * theta_y.cos(),
-            );
-            trig.normals[2] = Vec3::new(
-                trig.normals[2].x * theta_y.cos() + trig.normals[2].z * theta_y.sin(),
-                trig.normals[2].y,
-                -trig.normals[2].x * theta_y.sin() + trig.normals[2].z * theta_y.cos(),
-            );
-            trig.normal = unit_vector(trig.normal);
-            trig.normals[0] = unit_vector(trig.normals[0]);
-            trig.normals[1] = unit_vector(trig.normals[1]);
-            trig.normals[2] = unit_vector(trig.normals[2]);
-
-            // Rotate on z
-            trig.normal = Vec3::new(
-                trig.normal.x * theta_z.cos() - trig.normal.y * theta_z.sin(),
-                trig.normal.x * theta_z.sin() + trig.normal.y * theta_z.cos(),
-                trig.normal.z,
-            );
-            trig.normals[0] = Vec3::new(
-                trig.normals[0].x * theta_z.cos() - trig.normals[0].y * theta_z.sin(),
-                trig.normals[0].x * theta_z.sin() + trig.normals[0].y * theta_z.cos(),
-                trig.normals[0].z,
-            );
-            trig.normals[1] = Vec3::new(
-                trig.normals[1].x * theta_z.cos() - trig.normals[1].y * theta_z.sin(),
-                trig.normals[1].x * theta_z.sin() + trig.normals[1].y * theta_z.cos(),
-                trig.normals[1].z,
-            );
-            trig.normals[2] = Vec3::new(
-                trig.normals[2].x * theta_z.cos() - trig.normals[2].y * theta_z.sin(),
-                trig.normals[2].x * theta_z.sin() + trig.normals[2].y * theta_z.cos(),
-                trig.normals[2].z,
-            );
-            trig.normal = unit_vector(trig.normal);
-            trig.normals[0] = unit_vector(trig.normals[0]);
-            trig.normals[1] = unit_vector(trig.normals[1]);
-            trig.normals[2] = unit_vector(trig.normals[2]);
-
-            // Now rotate the individual points
-            for point in trig.points.iter_mut() {
-                // Rotate on x.
-                *point = Vec3::new(
-                    point.x,
-                    point.y * theta_x.cos() - point.z * theta_x.sin(),
-                    point.y * theta_x.sin() + point.z * theta_x.cos(),
-                );
-
-                // Rotate on y.
-                *point = Vec3::new(
-                    point.x * theta_y.cos() + point.z * theta_y.sin(),
-                    point.y,
-                    -point.x * theta_y.sin() + point.z * theta_y.cos(),
-                );
-
-                // Rotate on z.
-                *point = Vec3::new(
-                    point.x * theta_z.cos() - point.y * theta_z.sin(),
-                    point.x * theta_z.sin() + point.y * theta_z.cos(),
-                    point.z,
-                );
+        self.transform(&Transform::rotate_euler(r));
+    }
+
+    /// Generate smooth vertex normals, replacing any existing ones
+    /// # Arguments
+    /// * 'crease_angle' - Faces meeting at a larger angle than this, in degrees, keep a hard edge
+    /// * 'weighting' - How each face's normal contributes to the vertices it touches
+    /// # Notes
+    /// * Vertices are welded by position, so seams in the source file don't show up as hard edges
+    /// * Every face becomes smooth shaded, its flat normal is set to its geometric normal
+    pub fn generate_normals(&mut self, crease_angle: f64, weighting: NormalWeighting) {
+        // Quantize positions so vertices written slightly differently still weld
+        const WELD_EPSILON: f64 = 1e-6;
+        let key = |p: Vec3| {
+            [
+                (p.x / WELD_EPSILON).round() as i64,
+                (p.y / WELD_EPSILON).round() as i64,
+                (p.z / WELD_EPSILON).round() as i64,
+            ]
+        };
+
+        // Every (face, corner) pair touching each welded vertex
+        let mut vertices: HashMap<[i64; 3], Vec<(usize, usize)>> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for (corner, &p) in face.positions.iter().enumerate() {
+                vertices
+                    .entry(key(self.positions[p]))
+                    .or_default()
+                    .push((f, corner));
+            }
+        }
+
+        let points: Vec<[Vec3; 3]> = self.faces.iter().map(|face| self.points(face)).collect();
+        let face_normals: Vec<Vec3> = points.iter().map(|&p| geometric_normal(p)).collect();
+        let weights: Vec<[f64; 3]> = points
+            .iter()
+            .map(|&p| weighting.corner_weights(p))
+            .collect();
+        let min_cos = crease_angle.to_radians().cos();
+
+        let mut corner_normals = vec![[Vec3::new(0.0, 0.0, 0.0); 3]; self.faces.len()];
+        for corners in vertices.values() {
+            for &(f, corner) in corners.iter() {
+                // Only average faces which are within the crease angle of this one
+                let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                for &(other, other_corner) in corners.iter() {
+                    if dot(face_normals[f], face_normals[other]) >= min_cos {
+                        sum = sum + face_normals[other] * weights[other][other_corner];
+                    }
+                }
+
+                corner_normals[f][corner] = if sum.near_zero() {
+                    face_normals[f]
+                } else {
+                    unit_vector(sum)
+                };
+            }
+        }
+
+        // Rebuild the normals, sharing identical ones between faces
+        let mut normals: Vec<Vec3> = Vec::new();
+        let mut indices: HashMap<[u64; 3], usize> = HashMap::new();
+        let mut index = |n: Vec3| {
+            *indices
+                .entry([n.x.to_bits(), n.y.to_bits(), n.z.to_bits()])
+                .or_insert_with(|| {
+                    normals.push(n);
+                    normals.len() - 1
+                })
+        };
+
+        for (f, face) in self.faces.iter_mut().enumerate() {
+            face.normal = index(face_normals[f]);
+            face.normals = corner_normals[f].map(&mut index);
+            face.smooth = true;
+        }
+        self.normals = normals;
+    }
+}
+
+/// How face normals are weighted when averaging them into vertex normals
+/// * 'Area' - Larger faces pull the normal further towards themselves
+/// * 'Angle' - Faces are weighted by their angle at the vertex, independent of tessellation
+#[derive(Copy, Clone, Debug)]
+pub enum NormalWeighting {
+    Area,
+    Angle,
+}
+
+impl NormalWeighting {
+    /// Calculate the weight of each corner of a triangle
+    fn corner_weights(self, points: [Vec3; 3]) -> [f64; 3] {
+        match self {
+            NormalWeighting::Area => {
+                let area = cross(points[1] - points[0], points[2] - points[0]).length() * 0.5;
+                [area; 3]
+            }
+            NormalWeighting::Angle => {
+                let angle = |corner: usize| {
+                    let p = points[corner];
+                    let a = points[(corner + 1) % 3] - p;
+                    let b = points[(corner + 2) % 3] - p;
+                    let lengths = a.length() * b.length();
+                    if lengths == 0.0 {
+                        0.0
+                    } else {
+                        (dot(a, b) / lengths).clamp(-1.0, 1.0).acos()
+                    }
+                };
+                [angle(0), angle(1), angle(2)]
             }
         }
     }
@@ -211,123 +380,55 @@ impl Mesh {
     pub fn hit(&self, r: Ray) -> Hit {
         // We want to store the closest hit triangle so we only draw those
         let mut closest_hit = Hit::new();
-
-        // Loop through every triangle within the mesh
-        for trig in self.triangles.iter() {
-            // Check if the ray has hit any of the triangles within the mesh
-            let hit: Hit = trig.hit(r);
-            if hit.t > 0.0 {
-                // Check if the hit triangle is closer than the current closest
-                if hit.at.z > closest_hit.at.z {
-                    closest_hit = hit;
-                    closest_hit.material = self.material.clone();
+        let mut closest_face = None;
+
+        // Loop through every face within the mesh
+        for (f, face) in self.faces.iter().enumerate() {
+            // Check if the ray has hit any of the faces within the mesh
+            if let Some(t) = intersect(self.points(face), r) {
+                // Check if the hit face is closer along the ray than the current closest
+                if closest_hit.t < 0.0 || t < closest_hit.t {
+                    closest_hit.t = t;
+                    closest_hit.at = r.at(t);
+                    closest_face = Some(f);
                 }
             }
         }
+
+        // Only the closest face needs its triangle built
+        if let Some(f) = closest_face {
+            closest_hit.triangle = self.triangle(f);
+            closest_hit.material = self.material_for(&closest_hit.triangle).clone();
+        }
         closest_hit
     }
 }
 
+/// Errors produced while loading a mesh from a file
 #[derive(Debug, Error)]
 pub enum MeshError {
-    #[error("Failed to open mesh file: {0}")]
-    OpenFile(#[from] std::io::Error),
-
-    #[error("Failed to parse mesh data: {0}")]
-    ParseInt(#[from] std::num::ParseIntError),
-
-    #[error("Failed to parse mesh data: {0}")]
-    ParseFloat(#[from] std::num::ParseFloatError),
-}
-
-/// Load an OBJ mesh
-/// # Arguments
-/// * 'path' - Path of an OBJ file
-/// * 'smooth' - Boolean which states if the mesh is smooth shaded
-/// # Returns
-/// * A mesh and all of its triangles, including a default material
-pub fn load_mesh(path: &str, smooth: bool) -> Result<Mesh, MeshError> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-
-    // Will store all vertices, normals, and triangles
-    let mut vertices: Vec<[f64; 3]> = Vec::new();
-    let mut normals: Vec<[f64; 3]> = Vec::new();
-    let mut triangles: Vec<Triangle> = Vec::new();
-
-    // For each line in the obj file
-    for line in reader.lines() {
-        let line = line?;
-
-        // Split by white space
-        let words: Vec<&str> = line.split_whitespace().collect();
-
-        if words.is_empty() {
-            continue;
-        }
-
-        // If the first word is v, this means vertex
-        if words[0] == "v" {
-            // Push the vertex into the vertices vec
-            vertices.push([words[1].parse()?, words[2].parse()?, words[3].parse()?]);
-
-        // If it's a vertex normal
-        } else if words[0] == "vn" {
-            normals.push([words[1].parse()?, words[2].parse()?, words[3].parse()?]);
-
-        // If it's a face
-        } else if words[0] == "f" {
-            // Split by '/'
-            let v1: Vec<&str> = words[1].split('/').collect();
-            let v2: Vec<&str> = words[2].split('/').collect();
-            let v3: Vec<&str> = words[3].split('/').collect();
-
-            // Match the points and the normals
-            let p1: usize = v1[0].parse()?;
-            let n1: usize = v1[2].parse()?;
-
-            let p2: usize = v2[0].parse()?;
-            let n2: usize = v2[2].parse()?;
-
-            let p3: usize = v3[0].parse()?;
-            let n3: usize = v3[2].parse()?;
-
-            // Create a new triangle
-            let mut trig = Triangle::new(
-                Vec3::new(
-                    vertices[p1 - 1][0],
-                    vertices[p1 - 1][1],
-                    vertices[p1 - 1][2],
-                ),
-                Vec3::new(
-                    vertices[p2 - 1][0],
-                    vertices[p2 - 1][1],
-                    vertices[p2 - 1][2],
-                ),
-                Vec3::new(
-                    vertices[p3 - 1][0],
-                    vertices[p3 - 1][1],
-                    vertices[p3 - 1][2],
-                ),
-                Vec3::new(normals[n1 - 1][0], normals[n1 - 1][1], normals[n1 - 1][2]),
-            );
-
-            // If it's a smoothly shaded mesh, add to the triangle normals
-            if smooth {
-                trig.smooth = true;
-
-                trig.normals = [
-                    Vec3::new(normals[n1 - 1][0], normals[n1 - 1][1], normals[n1 - 1][2]),
-                    Vec3::new(normals[n2 - 1][0], normals[n2 - 1][1], normals[n2 - 1][2]),
-                    Vec3::new(normals[n3 - 1][0], normals[n3 - 1][1], normals[n3 - 1][2]),
-                ];
-            }
-
-            // Push the triangle to the vec
-            triangles.push(trig);
-        }
-    }
-
-    // Return the new mesh based on the triangles
-    Ok(Mesh::new_mesh(triangles))
+    #[error("Failed to open mesh file {path}: {source}")]
+    OpenFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to read mesh file {path}: {source}")]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse mesh file {path} at line {line}: {reason}")]
+    Parse {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+
+    #[error("Failed to import glTF file {path}: {source}")]
+    Gltf { path: String, source: gltf::Error },
+
+    #[error("Invalid mesh data in {path}: {reason}")]
+    Invalid { path: String, reason: String },
 }
@@ -0,0 +1,594 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    material::{Diffuse, MaterialEnum, Metal},
+    mesh::{Face, MeshError},
+    triangle::geometric_normal,
+    Mesh, Node, Vec3,
+};
+
+/// A named part of an OBJ file, one for each 'o' or 'g' statement
+#[derive(Clone, Debug)]
+pub struct ObjObject {
+    /// The object or group name, "default" for faces before any 'o' or 'g'
+    pub name: String,
+    /// The object's triangles, with one material table entry per material it uses
+    pub mesh: Mesh,
+}
+
+/// A single corner of an OBJ face with its indices resolved to 0-based positions
+#[derive(Copy, Clone, Debug)]
+struct FaceVertex {
+    /// Index into the vertex positions
+    position: usize,
+    /// Index into the texture coordinates, if given
+    uv: Option<usize>,
+    /// Index into the vertex normals, if given
+    normal: Option<usize>,
+}
+
+/// A triangulated OBJ face, still indexing into the whole file's vertex data
+#[derive(Copy, Clone, Debug)]
+struct ObjFace {
+    corners: [FaceVertex; 3],
+    smooth: bool,
+    /// Index into the group's material names
+    material: Option<usize>,
+}
+
+/// Faces collected for a single object or group while parsing
+struct ObjGroup {
+    name: String,
+    faces: Vec<ObjFace>,
+    /// Names of the materials used, indexed by the faces' material index
+    material_names: Vec<String>,
+}
+
+/// Everything read from an OBJ file
+struct ObjFile {
+    /// Vertex positions, normals and texture coordinates of the whole file
+    vertices: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<[f64; 2]>,
+    groups: Vec<ObjGroup>,
+    /// Paths of the referenced MTL files, relative to the OBJ file
+    material_libraries: Vec<String>,
+}
+
+/// Load an OBJ mesh
+/// # Arguments
+/// * 'path' - Path of an OBJ file
+/// * 'smooth' - Boolean which states if the mesh is smooth shaded
+/// # Returns
+/// * A mesh and all of its triangles, including a default material
+/// # Notes
+/// * Faces may be written as 'v', 'v/vt', 'v//vn' or 'v/vt/vn', with negative indices counting back from the last element
+/// * Quads and n-gons are triangulated as a fan around their first vertex
+/// * Faces without normals use the geometric face normal
+pub fn load_mesh(path: &str, smooth: bool) -> Result<Mesh, MeshError> {
+    let obj = parse_obj(path, Some(smooth))?;
+
+    // Flatten every group into one mesh using the default material
+    let faces: Vec<ObjFace> = obj
+        .groups
+        .iter()
+        .flat_map(|group| group.faces.iter())
+        .map(|&face| ObjFace {
+            material: None,
+            ..face
+        })
+        .collect();
+
+    // Return the new mesh based on the faces
+    Ok(build_mesh(&obj, &faces))
+}
+
+/// Load every object and group of an OBJ file as separate meshes
+/// # Arguments
+/// * 'path' - Path of an OBJ file
+/// # Returns
+/// * One named mesh per object or group which has faces, in file order
+/// # Notes
+/// * Materials are read from the MTL file referenced by each 'mtllib', relative to the OBJ file
+/// * Faces are smooth shaded when their smoothing group ('s') is on
+pub fn load_obj_scene(path: &str) -> Result<Vec<ObjObject>, MeshError> {
+    let obj = parse_obj(path, None)?;
+
+    // Read every material library the file references
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut materials: HashMap<String, MaterialEnum> = HashMap::new();
+    for library in obj.material_libraries.iter() {
+        let library_path = directory.join(library);
+        materials.extend(load_mtl(&library_path.to_string_lossy())?);
+    }
+
+    let objects = obj
+        .groups
+        .iter()
+        .map(|group| {
+            let mut mesh = build_mesh(&obj, &group.faces);
+
+            // Unknown material names keep the mesh's default material
+            for name in group.material_names.iter() {
+                let material = materials.get(name).unwrap_or(&mesh.material).clone();
+                mesh.add_material(material);
+            }
+
+            ObjObject {
+                name: group.name.clone(),
+                mesh,
+            }
+        })
+        .collect();
+
+    Ok(objects)
+}
+
+/// Load every object and group of an OBJ file as a scene graph
+/// # Arguments
+/// * 'path' - Path of an OBJ file
+/// # Returns
+/// * A root node named after the file, with one child node per object or group in file order
+/// # Notes
+/// * The objects are loaded like load_obj_scene, their nodes have identity transforms
+pub fn load_obj_graph(path: &str) -> Result<Node, MeshError> {
+    let name = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "root".to_string());
+
+    let mut root = Node::new(&name);
+    for object in load_obj_scene(path)? {
+        root.children
+            .push(Node::new(&object.name).with_mesh(Arc::new(object.mesh)));
+    }
+    Ok(root)
+}
+
+/// Parse an OBJ file into its groups
+/// # Arguments
+/// * 'path' - Path of an OBJ file
+/// * 'smooth' - Force smooth or flat shading, None follows the file's smoothing groups
+fn parse_obj(path: &str, smooth: Option<bool>) -> Result<ObjFile, MeshError> {
+    let file = File::open(path).map_err(|source| MeshError::OpenFile {
+        path: path.to_string(),
+        source,
+    })?;
+    let reader = BufReader::new(file);
+
+    // Will store all vertices, normals and texture coordinates
+    let mut obj = ObjFile {
+        vertices: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        groups: vec![ObjGroup::new("default")],
+        material_libraries: Vec::new(),
+    };
+    let mut material: Option<String> = None;
+    let mut smoothing_group = false;
+
+    // For each line in the obj file
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|source| MeshError::ReadFile {
+            path: path.to_string(),
+            source,
+        })?;
+        let parse_error = |reason: String| MeshError::Parse {
+            path: path.to_string(),
+            line: index + 1,
+            reason,
+        };
+
+        // Drop comments and split by white space
+        let data = line.split('#').next().unwrap_or_default();
+        let words: Vec<&str> = data.split_whitespace().collect();
+
+        if words.is_empty() {
+            continue;
+        }
+
+        match words[0] {
+            // Vertex position, any extra w or color components are ignored
+            "v" => obj
+                .vertices
+                .push(parse_vec3(&words[1..], "vertex").map_err(parse_error)?),
+
+            // Vertex normal
+            "vn" => obj
+                .normals
+                .push(parse_vec3(&words[1..], "normal").map_err(parse_error)?),
+
+            // Texture coordinate, v defaults to 0 when omitted
+            "vt" => {
+                let u = parse_float(words.get(1).copied(), "texture coordinate");
+                let v = match words.get(2) {
+                    Some(word) => parse_float(Some(word), "texture coordinate"),
+                    None => Ok(0.0),
+                };
+                obj.uvs
+                    .push([u.map_err(parse_error)?, v.map_err(parse_error)?]);
+            }
+
+            // Face
+            "f" => {
+                let face = words[1..]
+                    .iter()
+                    .map(|word| {
+                        parse_face_vertex(
+                            word,
+                            obj.vertices.len(),
+                            obj.uvs.len(),
+                            obj.normals.len(),
+                        )
+                    })
+                    .collect::<Result<Vec<FaceVertex>, String>>()
+                    .map_err(parse_error)?;
+
+                if face.len() < 3 {
+                    return Err(parse_error(format!(
+                        "face has {} vertices, expected at least 3",
+                        face.len()
+                    )));
+                }
+
+                let group = obj.groups.last_mut().unwrap();
+                let material_index = material.as_deref().map(|name| group.material_index(name));
+
+                // Triangulate as a fan around the first vertex
+                for i in 1..face.len() - 1 {
+                    group.faces.push(ObjFace {
+                        corners: [face[0], face[i], face[i + 1]],
+                        smooth: smooth.unwrap_or(smoothing_group),
+                        material: material_index,
+                    });
+                }
+            }
+
+            // Start a new object or group
+            "o" | "g" => {
+                let name = if words.len() > 1 {
+                    words[1..].join(" ")
+                } else {
+                    "default".to_string()
+                };
+
+                // An 'o' directly followed by a 'g' only names one group
+                let group = obj.groups.last_mut().unwrap();
+                if group.faces.is_empty() {
+                    group.name = name;
+                } else {
+                    obj.groups.push(ObjGroup::new(&name));
+                }
+            }
+
+            // Smoothing group, 'off' or 0 disables smooth shading
+            "s" => smoothing_group = !matches!(words.get(1), None | Some(&"off") | Some(&"0")),
+
+            "usemtl" => material = Some(words[1..].join(" ")),
+
+            // The rest of the line is one path, so file names may contain spaces
+            "mtllib" => {
+                let library = data.trim_start()["mtllib".len()..].trim();
+                if !library.is_empty() {
+                    obj.material_libraries.push(library.to_string());
+                }
+            }
+
+            // Anything else doesn't affect the geometry
+            _ => {}
+        }
+    }
+
+    obj.groups.retain(|group| !group.faces.is_empty());
+    Ok(obj)
+}
+
+impl ObjGroup {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            faces: Vec::new(),
+            material_names: Vec::new(),
+        }
+    }
+
+    /// Find the group's material index for a material name, adding it if it's new
+    fn material_index(&mut self, name: &str) -> usize {
+        match self.material_names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                self.material_names.push(name.to_string());
+                self.material_names.len() - 1
+            }
+        }
+    }
+}
+
+/// Load the materials of an MTL file
+/// # Arguments
+/// * 'path' - Path of an MTL file
+/// # Returns
+/// * The materials by name
+/// # Notes
+/// * 'Kd' gives the albedo color
+/// * Materials with 'Pm' of at least 0.5 or 'illum 3' (reflections) become metal, the rest diffuse
+/// * Metal smoothness is read from 'Pr', falling back to the shininess 'Ns' as written by Blender
+fn load_mtl(path: &str) -> Result<HashMap<String, MaterialEnum>, MeshError> {
+    let file = File::open(path).map_err(|source| MeshError::OpenFile {
+        path: path.to_string(),
+        source,
+    })?;
+    let reader = BufReader::new(file);
+
+    /// Properties collected for the material currently being defined
+    struct MtlMaterial {
+        name: String,
+        albedo: Vec3,
+        metallic: bool,
+        roughness: Option<f64>,
+        shininess: Option<f64>,
+    }
+
+    impl MtlMaterial {
+        fn build(&self) -> MaterialEnum {
+            if self.metallic {
+                // Blender writes Ns = (1 - roughness)^2 * 1000
+                let smoothness = self
+                    .roughness
+                    .or(self
+                        .shininess
+                        .map(|ns| 1.0 - (ns / 1000.0).clamp(0.0, 1.0).sqrt()))
+                    .unwrap_or(0.0);
+                MaterialEnum::Metal(Metal::new(self.albedo, smoothness))
+            } else {
+                MaterialEnum::Diffuse(Diffuse::new(self.albedo))
+            }
+        }
+    }
+
+    let mut materials: HashMap<String, MaterialEnum> = HashMap::new();
+    let mut current: Option<MtlMaterial> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|source| MeshError::ReadFile {
+            path: path.to_string(),
+            source,
+        })?;
+        let parse_error = |reason: String| MeshError::Parse {
+            path: path.to_string(),
+            line: index + 1,
+            reason,
+        };
+
+        let data = line.split('#').next().unwrap_or_default();
+        let words: Vec<&str> = data.split_whitespace().collect();
+
+        if words.is_empty() {
+            continue;
+        }
+
+        if words[0] == "newmtl" {
+            if let Some(material) = current.take() {
+                materials.insert(material.name.clone(), material.build());
+            }
+            current = Some(MtlMaterial {
+                name: words[1..].join(" "),
+                albedo: Vec3::new(0.8, 0.8, 0.8),
+                metallic: false,
+                roughness: None,
+                shininess: None,
+            });
+            continue;
+        }
+
+        // Every other statement describes the current material
+        let Some(material) = current.as_mut() else {
+            continue;
+        };
+        match words[0] {
+            // A single value is a gray
+            "Kd" if words.len() == 2 => {
+                let gray = parse_float(Some(words[1]), "color").map_err(parse_error)?;
+                material.albedo = Vec3::new(gray, gray, gray);
+            }
+            "Kd" => material.albedo = parse_vec3(&words[1..], "color").map_err(parse_error)?,
+            "Ns" => {
+                material.shininess =
+                    Some(parse_float(words.get(1).copied(), "shininess").map_err(parse_error)?)
+            }
+            "Pr" => {
+                material.roughness =
+                    Some(parse_float(words.get(1).copied(), "roughness").map_err(parse_error)?)
+            }
+            "Pm" => {
+                material.metallic =
+                    parse_float(words.get(1).copied(), "metallic").map_err(parse_error)? >= 0.5
+            }
+            "illum" => material.metallic |= words.get(1) == Some(&"3"),
+            _ => {}
+        }
+    }
+
+    if let Some(material) = current {
+        materials.insert(material.name.clone(), material.build());
+    }
+
+    Ok(materials)
+}
+
+/// Parse a single float
+/// # Arguments
+/// * 'word' - The word to parse, None if the line ended early
+/// * 'what' - Name of the element being parsed, used in the error
+fn parse_float(word: Option<&str>, what: &str) -> Result<f64, String> {
+    let word = word.ok_or_else(|| format!("missing {} component", what))?;
+    word.parse()
+        .map_err(|_| format!("invalid {} component '{}'", what, word))
+}
+
+/// Parse the first three words as the x,y,z of a Vec3
+fn parse_vec3(words: &[&str], what: &str) -> Result<Vec3, String> {
+    Ok(Vec3::new(
+        parse_float(words.first().copied(), what)?,
+        parse_float(words.get(1).copied(), what)?,
+        parse_float(words.get(2).copied(), what)?,
+    ))
+}
+
+/// Resolve an OBJ index into a 0-based index
+/// # Arguments
+/// * 'word' - The 1-based index, negative values count back from the last element
+/// * 'count' - How many elements have been defined so far
+/// * 'what' - Name of the element being indexed, used in the error
+fn resolve_index(word: &str, count: usize, what: &str) -> Result<usize, String> {
+    let index: i64 = word
+        .parse()
+        .map_err(|_| format!("invalid {} index '{}'", what, word))?;
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} is out of range, {} defined so far",
+            what, index, count
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+/// Parse a face corner written as 'v', 'v/vt', 'v//vn' or 'v/vt/vn'
+fn parse_face_vertex(
+    word: &str,
+    vertex_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<FaceVertex, String> {
+    let parts: Vec<&str> = word.split('/').collect();
+    if parts.len() > 3 {
+        return Err(format!("invalid face vertex '{}'", word));
+    }
+
+    let position = resolve_index(parts[0], vertex_count, "vertex")?;
+    let uv = match parts.get(1) {
+        Some(part) if !part.is_empty() => {
+            Some(resolve_index(part, uv_count, "texture coordinate")?)
+        }
+        _ => None,
+    };
+    let normal = match parts.get(2) {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, normal_count, "normal")?),
+        _ => None,
+    };
+
+    Ok(FaceVertex {
+        position,
+        uv,
+        normal,
+    })
+}
+
+/// Build a mesh from faces, only keeping the vertex data they use
+/// # Arguments
+/// * 'obj' - The parsed file the faces index into
+/// * 'faces' - The faces of the mesh
+/// # Notes
+/// * Faces without a normal at every corner use the geometric normal
+/// * The flat normal is taken from the first corner
+fn build_mesh(obj: &ObjFile, faces: &[ObjFace]) -> Mesh {
+    let mut mesh = Mesh::new_mesh(Vec::new());
+
+    // Map the file's indices to the mesh's indices, adding the values on first use
+    fn remap<T: Copy>(
+        map: &mut HashMap<usize, usize>,
+        from: &[T],
+        to: &mut Vec<T>,
+        i: usize,
+    ) -> usize {
+        *map.entry(i).or_insert_with(|| {
+            to.push(from[i]);
+            to.len() - 1
+        })
+    }
+    let mut positions: HashMap<usize, usize> = HashMap::new();
+    let mut normals: HashMap<usize, usize> = HashMap::new();
+    let mut uvs: HashMap<usize, usize> = HashMap::new();
+
+    for obj_face in faces {
+        let corners = obj_face.corners;
+        let points = corners.map(|corner| {
+            remap(
+                &mut positions,
+                &obj.vertices,
+                &mut mesh.positions,
+                corner.position,
+            )
+        });
+
+        // Use the geometric normal unless every corner has its own normal
+        let corner_normals = match corners.map(|corner| corner.normal) {
+            [Some(n1), Some(n2), Some(n3)] => {
+                [n1, n2, n3].map(|n| remap(&mut normals, &obj.normals, &mut mesh.normals, n))
+            }
+            _ => {
+                mesh.normals.push(geometric_normal(
+                    corners.map(|corner| obj.vertices[corner.position]),
+                ));
+                [mesh.normals.len() - 1; 3]
+            }
+        };
+
+        let mut face = Face::new(points, corner_normals[0]);
+
+        // If it's a smoothly shaded face, add the vertex normals
+        if obj_face.smooth {
+            face.smooth = true;
+            face.normals = corner_normals;
+        }
+
+        if let [Some(uv1), Some(uv2), Some(uv3)] = corners.map(|corner| corner.uv) {
+            face.uvs = Some([uv1, uv2, uv3].map(|uv| remap(&mut uvs, &obj.uvs, &mut mesh.uvs, uv)));
+        }
+
+        face.material = obj_face.material;
+        mesh.faces.push(face);
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn mtllib_paths_keep_spaces_and_kd_can_be_gray() {
+        let dir = TempDir::new("obj_mtllib");
+        dir.write(
+            "two words.mtl",
+            "newmtl gray\nKd 0.25\nnewmtl red\nKd 1 0 0\n",
+        );
+        let obj = dir.write(
+            "scene.obj",
+            "mtllib two words.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl gray\nf 1 2 3\nusemtl red\nf 1 2 3\n",
+        );
+
+        let objects = load_obj_scene(&obj).unwrap();
+        let mesh = &objects[0].mesh;
+        let albedo = |index: usize| match &mesh.materials[index] {
+            MaterialEnum::Diffuse(diffuse) => diffuse.albedo,
+            other => panic!("expected a diffuse material, got {:?}", other),
+        };
+        assert_eq!([albedo(0).x, albedo(0).y, albedo(0).z], [0.25, 0.25, 0.25]);
+        assert_eq!([albedo(1).x, albedo(1).y, albedo(1).z], [1.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,586 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+};
+
+use crate::{
+    mesh::{Face, MeshError},
+    texture::srgb_to_linear,
+    triangle::geometric_normal,
+    Mesh, Vec3,
+};
+
+/// How the body of a PLY file is stored
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// Type of a single PLY value
+#[derive(Copy, Clone, Debug)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+/// A property of a PLY element, either one value or a list of values
+#[derive(Copy, Clone, Debug)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+/// An element declared in the header, like 'vertex' or 'face'
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// A vertex read from the body
+struct PlyVertex {
+    position: Vec3,
+    normal: Option<Vec3>,
+    color: Option<Vec3>,
+    uv: Option<[f64; 2]>,
+}
+
+/// Load a PLY mesh
+/// # Arguments
+/// * 'path' - Path of a PLY file, ASCII or binary in either byte order
+/// * 'smooth' - Boolean which states if the mesh is smooth shaded
+/// # Returns
+/// * A mesh and all of its triangles, including a default material
+/// # Notes
+/// * Vertex normals (nx, ny, nz), colors (red, green, blue) and texture coordinates (u, v or s, t) are read when present
+/// * Integer colors are treated as sRGB, float colors as linear
+/// * Faces with more than 3 vertices are triangulated as a fan around their first vertex
+pub fn load_ply(path: &str, smooth: bool) -> Result<Mesh, MeshError> {
+    let file = File::open(path).map_err(|source| MeshError::OpenFile {
+        path: path.to_string(),
+        source,
+    })?;
+    let mut reader = BufReader::new(file);
+    let read_error = |source| MeshError::ReadFile {
+        path: path.to_string(),
+        source,
+    };
+
+    let (format, elements, header_lines) = read_header(path, &mut reader)?;
+
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body).map_err(read_error)?;
+    let mut values = match format {
+        Format::Ascii => Values::ascii(&body, header_lines),
+        _ => Values::Binary {
+            data: &body,
+            offset: 0,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+    let body_error = |values: &Values, element: &str, index: usize, reason: String| {
+        let reason = format!("{} {}: {}", element, index, reason);
+        match values.line() {
+            Some(line) => MeshError::Parse {
+                path: path.to_string(),
+                line,
+                reason,
+            },
+            None => MeshError::Invalid {
+                path: path.to_string(),
+                reason,
+            },
+        }
+    };
+
+    let mut vertices: Vec<PlyVertex> = Vec::new();
+    let mut triangles: Vec<[usize; 3]> = Vec::new();
+
+    // Elements are stored one after another in the order of the header
+    for element in elements.iter() {
+        for index in 0..element.count {
+            let mut vertex = PlyVertex {
+                position: Vec3::new(0.0, 0.0, 0.0),
+                normal: None,
+                color: None,
+                uv: None,
+            };
+            let mut face: Vec<usize> = Vec::new();
+
+            for property in element.properties.iter() {
+                match property.kind {
+                    PropertyType::Scalar(ty) => {
+                        let value = values
+                            .read(ty)
+                            .map_err(|reason| body_error(&values, &element.name, index, reason))?;
+                        if element.name == "vertex" {
+                            vertex.set(&property.name, ty, value);
+                        }
+                    }
+                    PropertyType::List { count, item } => {
+                        let count = values
+                            .read(count)
+                            .map_err(|reason| body_error(&values, &element.name, index, reason))?;
+                        if count < 0.0 || count.fract() != 0.0 {
+                            return Err(body_error(
+                                &values,
+                                &element.name,
+                                index,
+                                format!("list length {} isn't a whole number", count),
+                            ));
+                        }
+                        for _ in 0..count as usize {
+                            let value = values.read(item).map_err(|reason| {
+                                body_error(&values, &element.name, index, reason)
+                            })?;
+                            if element.name == "face"
+                                && matches!(&*property.name, "vertex_indices" | "vertex_index")
+                            {
+                                if value < 0.0 || value.fract() != 0.0 {
+                                    return Err(body_error(
+                                        &values,
+                                        "face",
+                                        index,
+                                        format!("vertex index {} isn't a whole number", value),
+                                    ));
+                                }
+                                face.push(value as usize);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                vertices.push(vertex);
+            } else if element.name == "face" {
+                if let Some(bad) = face.iter().find(|i| **i >= vertices.len()) {
+                    return Err(body_error(
+                        &values,
+                        "face",
+                        index,
+                        format!(
+                            "vertex index {} is out of range, {} vertices defined",
+                            bad,
+                            vertices.len()
+                        ),
+                    ));
+                }
+
+                // Triangulate as a fan around the first vertex
+                for i in 1..face.len().saturating_sub(1) {
+                    triangles.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+        }
+    }
+
+    // Return the new mesh based on the vertices and triangles
+    Ok(build_mesh(&vertices, &triangles, smooth))
+}
+
+/// Read the header, up to and including 'end_header'
+/// # Returns
+/// * The body format, the declared elements and how many lines the header took
+fn read_header(
+    path: &str,
+    reader: &mut BufReader<File>,
+) -> Result<(Format, Vec<Element>, usize), MeshError> {
+    let mut format: Option<Format> = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|source| MeshError::ReadFile {
+                path: path.to_string(),
+                source,
+            })?;
+        line_number += 1;
+        let parse_error = |reason: String| MeshError::Parse {
+            path: path.to_string(),
+            line: line_number,
+            reason,
+        };
+
+        if read == 0 {
+            return Err(parse_error("file ended before 'end_header'".to_string()));
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if line_number == 1 {
+            if words != ["ply"] {
+                return Err(parse_error("file doesn't start with 'ply'".to_string()));
+            }
+            continue;
+        }
+        if words.is_empty() {
+            continue;
+        }
+
+        match words[0] {
+            "format" => {
+                format = Some(match words.get(1) {
+                    Some(&"ascii") => Format::Ascii,
+                    Some(&"binary_little_endian") => Format::BinaryLittleEndian,
+                    Some(&"binary_big_endian") => Format::BinaryBigEndian,
+                    _ => {
+                        return Err(parse_error(format!(
+                            "unknown format '{}'",
+                            words[1..].join(" ")
+                        )))
+                    }
+                })
+            }
+            "element" => {
+                let (Some(name), Some(count)) = (words.get(1), words.get(2)) else {
+                    return Err(parse_error("element needs a name and a count".to_string()));
+                };
+                let count = count
+                    .parse()
+                    .map_err(|_| parse_error(format!("invalid element count '{}'", count)))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            "property" => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| parse_error("property before any element".to_string()))?;
+                let scalar = |word: Option<&&str>| {
+                    word.and_then(|word| scalar_type(word)).ok_or_else(|| {
+                        parse_error(format!("invalid property '{}'", words[1..].join(" ")))
+                    })
+                };
+                let (kind, name) = if words.get(1) == Some(&"list") {
+                    let kind = PropertyType::List {
+                        count: scalar(words.get(2))?,
+                        item: scalar(words.get(3))?,
+                    };
+                    (kind, words.get(4))
+                } else {
+                    (PropertyType::Scalar(scalar(words.get(1))?), words.get(2))
+                };
+                let name = name.ok_or_else(|| parse_error("property has no name".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            "end_header" => {
+                let format =
+                    format.ok_or_else(|| parse_error("header has no format".to_string()))?;
+                return Ok((format, elements, line_number));
+            }
+            // Comments and obj_info don't affect the geometry
+            _ => {}
+        }
+    }
+}
+
+/// Parse a PLY type name, accepting both the old and the sized names
+fn scalar_type(word: &str) -> Option<ScalarType> {
+    Some(match word {
+        "char" | "int8" => ScalarType::I8,
+        "uchar" | "uint8" => ScalarType::U8,
+        "short" | "int16" => ScalarType::I16,
+        "ushort" | "uint16" => ScalarType::U16,
+        "int" | "int32" => ScalarType::I32,
+        "uint" | "uint32" => ScalarType::U32,
+        "float" | "float32" => ScalarType::F32,
+        "double" | "float64" => ScalarType::F64,
+        _ => return None,
+    })
+}
+
+/// Reads the values of the body one at a time
+enum Values<'a> {
+    /// Whitespace separated words and the line they're on
+    Ascii {
+        words: Vec<(usize, &'a str)>,
+        next: usize,
+    },
+    Binary {
+        data: &'a [u8],
+        offset: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Values<'a> {
+    /// Split an ASCII body into words
+    /// # Arguments
+    /// * 'body' - Everything after the header
+    /// * 'header_lines' - How many lines the header took, to number the body lines
+    fn ascii(body: &'a [u8], header_lines: usize) -> Self {
+        // Non UTF-8 data will fail to parse as numbers anyway
+        let text = std::str::from_utf8(body).unwrap_or_default();
+        let words = text
+            .lines()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                line.split_whitespace()
+                    .map(move |word| (header_lines + i + 1, word))
+            })
+            .collect();
+        Values::Ascii { words, next: 0 }
+    }
+
+    /// The line of the last value read, None for binary files
+    fn line(&self) -> Option<usize> {
+        match self {
+            Values::Ascii { words, next } => words
+                .get(next.saturating_sub(1))
+                .or(words.last())
+                .map(|(line, _)| *line),
+            Values::Binary { .. } => None,
+        }
+    }
+
+    /// Read the next value
+    fn read(&mut self, ty: ScalarType) -> Result<f64, String> {
+        match self {
+            Values::Ascii { words, next } => {
+                let (_, word) = words
+                    .get(*next)
+                    .ok_or_else(|| "file ended early".to_string())?;
+                *next += 1;
+                word.parse()
+                    .map_err(|_| format!("invalid value '{}'", word))
+            }
+            Values::Binary {
+                data,
+                offset,
+                big_endian,
+            } => {
+                let size = match ty {
+                    ScalarType::I8 | ScalarType::U8 => 1,
+                    ScalarType::I16 | ScalarType::U16 => 2,
+                    ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+                    ScalarType::F64 => 8,
+                };
+                let bytes = data
+                    .get(*offset..*offset + size)
+                    .ok_or_else(|| "file ended early".to_string())?;
+                *offset += size;
+
+                // Put the bytes in little endian order
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    b[..size].reverse();
+                }
+
+                Ok(match ty {
+                    ScalarType::I8 => b[0] as i8 as f64,
+                    ScalarType::U8 => b[0] as f64,
+                    ScalarType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    ScalarType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    ScalarType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+impl PlyVertex {
+    /// Store a vertex property by its name, unknown properties are ignored
+    fn set(&mut self, name: &str, ty: ScalarType, value: f64) {
+        // Integer colors are 0-255 (or 0-65535) and sRGB encoded
+        let color = |value: f64| match ty {
+            ScalarType::F32 | ScalarType::F64 => value,
+            ScalarType::U16 | ScalarType::I16 => srgb_to_linear(value / 65535.0),
+            _ => srgb_to_linear(value / 255.0),
+        };
+
+        match name {
+            "x" => self.position.x = value,
+            "y" => self.position.y = value,
+            "z" => self.position.z = value,
+            "nx" => self.normal.get_or_insert(Vec3::new(0.0, 0.0, 0.0)).x = value,
+            "ny" => self.normal.get_or_insert(Vec3::new(0.0, 0.0, 0.0)).y = value,
+            "nz" => self.normal.get_or_insert(Vec3::new(0.0, 0.0, 0.0)).z = value,
+            "red" | "r" | "diffuse_red" => {
+                self.color.get_or_insert(Vec3::new(0.0, 0.0, 0.0)).x = color(value)
+            }
+            "green" | "g" | "diffuse_green" => {
+                self.color.get_or_insert(Vec3::new(0.0, 0.0, 0.0)).y = color(value)
+            }
+            "blue" | "b" | "diffuse_blue" => {
+                self.color.get_or_insert(Vec3::new(0.0, 0.0, 0.0)).z = color(value)
+            }
+            "u" | "s" | "texture_u" | "texture_s" => self.uv.get_or_insert([0.0, 0.0])[0] = value,
+            "v" | "t" | "texture_v" | "texture_t" => self.uv.get_or_insert([0.0, 0.0])[1] = value,
+            _ => {}
+        }
+    }
+}
+
+/// Create a mesh sharing the vertices between its triangles
+/// # Arguments
+/// * 'vertices' - Every vertex of the file, the mesh's vertex arrays use the same indices
+/// * 'triangles' - Vertex indices of each triangle
+/// * 'smooth' - Boolean which states if the mesh is smooth shaded
+/// # Notes
+/// * Normals, colors and texture coordinates are only used if every vertex has them
+/// * The flat normals are the geometric normals, added after the vertex normals
+fn build_mesh(vertices: &[PlyVertex], triangles: &[[usize; 3]], smooth: bool) -> Mesh {
+    let mut mesh = Mesh::new_mesh(Vec::new());
+    mesh.positions = vertices.iter().map(|vertex| vertex.position).collect();
+
+    // The properties are declared once for all vertices, so they either all have them or none do
+    let normals: Option<Vec<Vec3>> = vertices.iter().map(|vertex| vertex.normal).collect();
+    let colors: Option<Vec<Vec3>> = vertices.iter().map(|vertex| vertex.color).collect();
+    let uvs: Option<Vec<[f64; 2]>> = vertices.iter().map(|vertex| vertex.uv).collect();
+    let has_normals = smooth && normals.is_some();
+    let has_colors = colors.is_some();
+    let has_uvs = uvs.is_some();
+    if has_normals {
+        mesh.normals = normals.unwrap_or_default();
+    }
+    mesh.colors = colors.unwrap_or_default();
+    mesh.uvs = uvs.unwrap_or_default();
+
+    for &corners in triangles {
+        mesh.normals
+            .push(geometric_normal(corners.map(|i| mesh.positions[i])));
+        let mut face = Face::new(corners, mesh.normals.len() - 1);
+
+        // If it's a smoothly shaded mesh, use the vertex normals when there are any
+        if smooth {
+            face.smooth = true;
+            if has_normals {
+                face.normals = corners;
+            }
+        }
+
+        if has_colors {
+            face.colors = Some(corners);
+        }
+        if has_uvs {
+            face.uvs = Some(corners);
+        }
+        mesh.faces.push(face);
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::Triangle;
+
+    fn xyz(v: Vec3) -> [f64; 3] {
+        [v.x, v.y, v.z]
+    }
+
+    /// A square with normals, as one quad face
+    const POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+
+    fn header(format: &str) -> String {
+        format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+             property float nx\nproperty float ny\nproperty float nz\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        )
+    }
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let mut data = header(if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        })
+        .into_bytes();
+        let float = |data: &mut Vec<u8>, v: f32| {
+            data.extend(if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            })
+        };
+        for p in POSITIONS {
+            p.iter().for_each(|&v| float(&mut data, v));
+            [0.0, 0.0, 1.0].iter().for_each(|&v| float(&mut data, v));
+        }
+        data.push(4);
+        for i in 0..4_i32 {
+            data.extend(if big_endian {
+                i.to_be_bytes()
+            } else {
+                i.to_le_bytes()
+            });
+        }
+        data
+    }
+
+    fn ascii(indices: &str) -> Vec<u8> {
+        let mut text = header("ascii");
+        for p in POSITIONS {
+            text += &format!("{} {} {} 0 0 1\n", p[0], p[1], p[2]);
+        }
+        text += &format!("4 {}\n", indices);
+        text.into_bytes()
+    }
+
+    fn load(name: &str, data: &[u8]) -> Result<Mesh, MeshError> {
+        let dir = TempDir::new(&format!("ply_{}", name));
+        load_ply(&dir.write("mesh.ply", data), true)
+    }
+
+    #[test]
+    fn ascii_and_binary_load_the_same_mesh() {
+        let meshes = [
+            load("ascii", &ascii("0 1 2 3")).unwrap(),
+            load("little", &binary(false)).unwrap(),
+            load("big", &binary(true)).unwrap(),
+        ];
+        for mesh in meshes.iter() {
+            assert_eq!(mesh.faces.len(), 2);
+            let triangles: Vec<Triangle> = mesh.triangles().collect();
+            let expected: Vec<Triangle> = meshes[0].triangles().collect();
+            for (trig, expected) in triangles.iter().zip(expected.iter()) {
+                assert_eq!(trig.points.map(xyz), expected.points.map(xyz));
+                assert_eq!(trig.normals.map(xyz), expected.normals.map(xyz));
+            }
+            assert_eq!(xyz(triangles[1].points[2]), [0.0, 1.0, 0.0]);
+            assert_eq!(xyz(triangles[0].normals[0]), [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn negative_and_fractional_indices_are_errors() {
+        assert!(load("negative", &ascii("0 1 2 -1")).is_err());
+        assert!(load("fraction", &ascii("0 1 2 2.5")).is_err());
+    }
+}
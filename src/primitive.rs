@@ -0,0 +1,160 @@
+use std::f64::consts::PI;
+
+use crate::{mesh::Face, Mesh, Vec3};
+
+/// Generate a sphere made of latitude and longitude lines
+/// # Arguments
+/// * 'radius' - Radius of the sphere, centered on the origin
+/// * 'segments' - Number of lines around the y axis, at least 3
+/// * 'rings' - Number of bands from pole to pole, at least 2
+/// # Returns
+/// * A smooth shaded mesh with texture coordinates wrapping once around the y axis
+pub fn uv_sphere(radius: f64, segments: u32, rings: u32) -> Mesh {
+    let segments = segments.max(3) as usize;
+    let rings = rings.max(2) as usize;
+    let mut mesh = Mesh::new_mesh(Vec::new());
+
+    // The seam column is duplicated, so the texture coordinates can wrap from 1 to 0
+    for ring in 0..=rings {
+        let v = ring as f64 / rings as f64;
+        let theta = v * PI;
+        for segment in 0..=segments {
+            let u = segment as f64 / segments as f64;
+            let phi = u * 2.0 * PI;
+            let normal = Vec3::new(
+                theta.sin() * phi.sin(),
+                theta.cos(),
+                theta.sin() * phi.cos(),
+            );
+            mesh.positions.push(normal * radius);
+            mesh.normals.push(normal);
+            mesh.uvs.push([u, 1.0 - v]);
+        }
+    }
+
+    let index = |ring: usize, segment: usize| ring * (segments + 1) + segment;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let top_left = index(ring, segment);
+            let top_right = index(ring, segment + 1);
+            let bottom_left = index(ring + 1, segment);
+            let bottom_right = index(ring + 1, segment + 1);
+
+            // The triangles touching the poles would be degenerate
+            let mut corners: Vec<[usize; 3]> = Vec::new();
+            if ring != 0 {
+                corners.push([top_left, bottom_left, top_right]);
+            }
+            if ring != rings - 1 {
+                corners.push([top_right, bottom_left, bottom_right]);
+            }
+
+            for corners in corners {
+                add_smooth_face(&mut mesh, corners);
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Generate a box
+/// # Arguments
+/// * 'size' - Vec3 which contains the x,y,z lengths of the box, centered on the origin
+/// # Returns
+/// * A flat shaded mesh, each side has texture coordinates covering 0-1
+pub fn cuboid(size: Vec3) -> Mesh {
+    let mut mesh = Mesh::new_mesh(Vec::new());
+    let half = size * 0.5;
+
+    // Each side's normal and the two directions across it, chosen so the corners wind counter-clockwise
+    let sides = [
+        (
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+        (
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+        (
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ),
+        (
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ),
+        (
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+        (
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+    ];
+
+    for (normal, u, v) in sides {
+        let scale = |d: Vec3| d * half;
+        let center = scale(normal);
+        add_quad(&mut mesh, center, scale(u), scale(v), normal);
+    }
+
+    mesh
+}
+
+/// Generate a flat rectangle in the xz plane facing up
+/// # Arguments
+/// * 'width' - Length along the x axis, centered on the origin
+/// * 'depth' - Length along the z axis, centered on the origin
+/// # Returns
+/// * A flat shaded mesh of two triangles with texture coordinates covering 0-1
+pub fn quad(width: f64, depth: f64) -> Mesh {
+    let mut mesh = Mesh::new_mesh(Vec::new());
+    add_quad(
+        &mut mesh,
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(width * 0.5, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -depth * 0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    mesh
+}
+
+/// Add a smooth shaded face whose normals and texture coordinates share the position indices
+fn add_smooth_face(mesh: &mut Mesh, corners: [usize; 3]) {
+    let mut face = Face::new(corners, corners[0]);
+    face.smooth = true;
+    face.normals = corners;
+    face.uvs = Some(corners);
+    mesh.faces.push(face);
+}
+
+/// Add a flat shaded rectangle as two triangles
+/// # Arguments
+/// * 'center' - Center of the rectangle
+/// * 'u, v' - Half of the rectangle's sides, u cross v points along the normal
+/// * 'normal' - Direction the rectangle faces
+fn add_quad(mesh: &mut Mesh, center: Vec3, u: Vec3, v: Vec3, normal: Vec3) {
+    let start = mesh.positions.len();
+    let uv_start = mesh.uvs.len();
+    for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+        mesh.positions.push(center + u * x + v * y);
+        mesh.uvs.push([(x + 1.0) * 0.5, (y + 1.0) * 0.5]);
+    }
+    mesh.normals.push(normal);
+    let normal = mesh.normals.len() - 1;
+
+    for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+        let mut face = Face::new([start + a, start + b, start + c], normal);
+        face.uvs = Some([uv_start + a, uv_start + b, uv_start + c]);
+        mesh.faces.push(face);
+    }
+}
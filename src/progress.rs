@@ -0,0 +1,117 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How far a render has come
+#[derive(Copy, Clone, Debug)]
+pub struct Progress {
+    /// Parts of the image which are finished, tiles or rows for run_sequential and each pass of render_progressive
+    pub completed: u32,
+    /// Parts of the image in total
+    pub total: u32,
+    /// Time since the render started
+    pub elapsed: Duration,
+    /// Estimated time until the render is finished
+    pub eta: Duration,
+}
+
+/// When a progressive render stops refining the image, at whichever limit is reached first
+/// # Notes
+/// * A budget without any limits renders until it's cancelled
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Budget {
+    /// Wall-clock time the render may take, the last pass is shortened to fit
+    pub time: Option<Duration>,
+    /// Samples per pixel in total, over all passes
+    pub samples: Option<u32>,
+    /// Average noise of the pixels at which the image is good enough, measured like AdaptiveSampling's noise_threshold
+    pub noise: Option<f64>,
+}
+
+/// A finished pass of a progressive render
+#[derive(Copy, Clone, Debug)]
+pub struct Pass {
+    /// Number of the pass, starting at 0
+    pub index: u32,
+    /// Samples per pixel taken so far
+    pub samples: u32,
+    /// Time since the render started
+    pub elapsed: Duration,
+    /// Average noise of the pixels, infinite until each pixel has 2 samples
+    pub noise: f64,
+}
+
+/// Function called with the progress of a render
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Flag used to stop a render from another thread
+/// # Notes
+/// * Clones share the flag, so one clone can be given to the ray tracer and another kept to cancel it
+/// * Tiles which have already started are finished before the render stops
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Create a new token which isn't cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the render to stop, it returns Error::Cancelled
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether cancel has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts the finished parts of a render, shared between the rendering threads
+pub(crate) struct ProgressTracker<'a> {
+    start: Instant,
+    completed: AtomicU32,
+    total: u32,
+    callback: Option<&'a ProgressCallback>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    /// Start tracking a render
+    /// # Arguments
+    /// * 'total' - Number of tiles or rows to render
+    /// * 'callback' - Called each time one is finished
+    pub(crate) fn new(total: u32, callback: Option<&'a ProgressCallback>) -> Self {
+        Self {
+            start: Instant::now(),
+            completed: AtomicU32::new(0),
+            total,
+            callback,
+        }
+    }
+
+    /// Mark a tile or row as finished and report the progress
+    pub(crate) fn done(&self) {
+        let Some(callback) = self.callback else {
+            return;
+        };
+
+        let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        let elapsed = self.start.elapsed();
+
+        // Assume the remaining parts take as long as the finished ones on average
+        let eta = elapsed.mul_f64((self.total - completed) as f64 / completed as f64);
+        callback(Progress {
+            completed,
+            total: self.total,
+            elapsed,
+            eta,
+        });
+    }
+}
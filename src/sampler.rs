@@ -0,0 +1,491 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Which sampler generates the random values of each sample
+/// * 'Independent' - Unrelated random values, converges the slowest
+/// * 'Stratified' - Random values jittered within evenly spread cells, one cell per sample
+/// * 'Halton' - Halton sequence, Owen-scrambled for each pixel
+/// * 'Sobol' - Owen-scrambled Sobol sequence, converges the fastest for most scenes
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Create a sampler of this kind
+    /// # Arguments
+    /// * 'seed' - Seed which all values are derived from
+    /// * 'samples' - Number of samples taken per pixel
+    pub fn create(self, seed: u64, samples: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+/// Random values used by a material to scatter a ray
+#[derive(Copy, Clone, Debug)]
+pub struct BounceSample {
+    /// Picks between the ways a material can scatter
+    pub choice: f64,
+    /// Picks the direction of the scattered ray
+    pub direction: [f64; 2],
+    /// Picks how far a direction is fuzzed
+    pub radius: f64,
+}
+
+/// Generates the random values of the samples of a pixel, each from 0-1
+/// # Notes
+/// * The values are drawn as dimensions in a fixed order, the pixel position, lens, time and then each bounce
+/// * Samplers other than the independent one spread the values of a pixel's samples evenly within each dimension
+/// * Values only depend on the seed, pixel, sample and dimension, so renders can be repeated
+pub trait Sampler {
+    /// Start a sample, the following values belong to it
+    /// # Arguments
+    /// * 'x, y' - The pixel
+    /// * 'sample' - Index of the sample within the pixel
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32);
+
+    /// The value of the next dimension
+    fn get_1d(&mut self) -> f64;
+
+    /// The values of the next two dimensions, spread evenly as a pair
+    fn get_2d(&mut self) -> [f64; 2];
+
+    /// Position within the pixel
+    fn pixel(&mut self) -> [f64; 2] {
+        self.get_2d()
+    }
+
+    /// Position on the camera's lens
+    fn lens(&mut self) -> [f64; 2] {
+        self.get_2d()
+    }
+
+    /// Time within the camera's shutter
+    fn time(&mut self) -> f64 {
+        self.get_1d()
+    }
+
+    /// Values for a material to scatter a ray at one bounce
+    fn bounce(&mut self) -> BounceSample {
+        BounceSample {
+            choice: self.get_1d(),
+            direction: self.get_2d(),
+            radius: self.get_1d(),
+        }
+    }
+
+    /// Value deciding whether a path survives Russian roulette after a bounce
+    fn roulette(&mut self) -> f64 {
+        self.get_1d()
+    }
+}
+
+/// Unrelated random values from a generator seeded for each sample
+pub struct IndependentSampler {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl IndependentSampler {
+    /// Create a new independent sampler
+    /// # Arguments
+    /// * 'seed' - Seed which all values are derived from
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32) {
+        self.rng =
+            StdRng::seed_from_u64(mix(self.seed ^ mix(pixel_key(x, y) ^ mix(sample as u64))));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [self.rng.gen(), self.rng.gen()]
+    }
+}
+
+/// Random values jittered within cells, each sample of a pixel gets its own cell in every dimension
+/// # Notes
+/// * The cells are shuffled differently for each dimension, so the dimensions don't line up
+pub struct StratifiedSampler {
+    seed: u64,
+    samples: u32,
+    pixel: u64,
+    sample: u32,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    /// Create a new stratified sampler
+    /// # Arguments
+    /// * 'seed' - Seed which all values are derived from
+    /// * 'samples' - Number of samples taken per pixel, which is the number of cells
+    pub fn new(seed: u64, samples: u32) -> Self {
+        Self {
+            seed,
+            samples: samples.max(1),
+            pixel: 0,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Hash of the current pixel and dimension, the same for all samples of the pixel
+    fn dimension_hash(&mut self) -> u64 {
+        let hash = mix(self.seed ^ mix(self.pixel ^ mix(self.dimension)));
+        self.dimension += 1;
+        hash
+    }
+
+    /// Random value from 0-1 which is different for each sample
+    fn jitter(&self, hash: u64, i: u64) -> f64 {
+        to_unit(mix(hash ^ mix(((self.sample as u64) << 2) | i)))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32) {
+        self.pixel = pixel_key(x, y);
+        self.sample = sample;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.dimension_hash();
+        let cell = permutation_element(self.sample % self.samples, self.samples, hash as u32);
+        (cell as f64 + self.jitter(hash, 0)) / self.samples as f64
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        // A grid with at least as many cells as samples, as square as possible
+        let columns = (self.samples as f64).sqrt().ceil() as u32;
+        let rows = self.samples.div_ceil(columns);
+
+        let hash = self.dimension_hash();
+        let cell = permutation_element(self.sample % self.samples, columns * rows, hash as u32);
+        [
+            ((cell % columns) as f64 + self.jitter(hash, 0)) / columns as f64,
+            ((cell / columns) as f64 + self.jitter(hash, 1)) / rows as f64,
+        ]
+    }
+}
+
+/// Primes used as the bases of the Halton sequence's dimensions
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, Owen-scrambled differently for each pixel
+/// # Notes
+/// * Dimensions past the primes in the table use random values
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    sample: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    /// Create a new Halton sampler
+    /// # Arguments
+    /// * 'seed' - Seed which the scrambling is derived from
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32) {
+        self.pixel = pixel_key(x, y);
+        self.sample = sample;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = mix(self.seed ^ mix(self.pixel ^ mix(self.dimension as u64)));
+        let value = match PRIMES.get(self.dimension) {
+            Some(&base) => scrambled_radical_inverse(base, self.sample, hash),
+            None => to_unit(mix(hash ^ mix(self.sample as u64))),
+        };
+        self.dimension += 1;
+        value
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [self.get_1d(), self.get_1d()]
+    }
+}
+
+/// The first two dimensions of the Sobol sequence, Owen-scrambled and shuffled for each pair of dimensions
+/// # Notes
+/// * Every 2D value uses its own scrambled copy of the sequence, as described by Burley in "Practical Hash-based Owen Scrambling"
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    sample: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    /// Create a new Sobol sampler
+    /// # Arguments
+    /// * 'seed' - Seed which the scrambling is derived from
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Hash of the current pixel and dimension, the same for all samples of the pixel
+    fn dimension_hash(&mut self) -> u64 {
+        let hash = mix(self.seed ^ mix(self.pixel ^ mix(self.dimension)));
+        self.dimension += 1;
+        hash
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32) {
+        self.pixel = pixel_key(x, y);
+        self.sample = sample;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.dimension_hash();
+        let index = owen_scramble(self.sample, hash as u32);
+        to_unit32(owen_scramble(index.reverse_bits(), (hash >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let hash = self.dimension_hash();
+        let second = mix(hash);
+        let index = owen_scramble(self.sample, hash as u32);
+        [
+            to_unit32(owen_scramble(index.reverse_bits(), (hash >> 32) as u32)),
+            to_unit32(owen_scramble(sobol_second(index), second as u32)),
+        ]
+    }
+}
+
+/// Scramble the bits of a value, used to derive unrelated values from neighbouring pixels, samples and dimensions
+/// # Notes
+/// * The finalizer of SplitMix64
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Combine a pixel's coordinates into one value
+fn pixel_key(x: u32, y: u32) -> u64 {
+    ((y as u64) << 32) | x as u64
+}
+
+/// Convert random bits to a value from 0-1, excluding 1
+fn to_unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Convert a 32 bit fraction to a value from 0-1, excluding 1
+fn to_unit32(bits: u32) -> f64 {
+    bits as f64 / (1u64 << 32) as f64
+}
+
+/// Mirror the digits of a number around the decimal point in a base, Owen-scrambling each digit
+/// # Arguments
+/// * 'base' - Base of the digits
+/// * 'index' - Number to mirror, e.g. 6 is 110 in base 2, so 0.011 in base 2 which is 0.375 before scrambling
+/// * 'hash' - Picks the scrambling
+/// # Returns
+/// * The value from 0-1, excluding 1
+/// # Notes
+/// * Each digit is permuted depending on the digits before it, which removes the correlation between dimensions with large bases
+fn scrambled_radical_inverse(base: u32, mut index: u32, hash: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut digit_weight = inverse_base;
+    let mut value = 0.0;
+    let mut prefix = hash;
+
+    // Leading zero digits are scrambled too, so keep going until the digits are too small to matter
+    while digit_weight > 1e-12 {
+        let digit = index % base;
+        value += permutation_element(digit, base, prefix as u32) as f64 * digit_weight;
+        prefix = mix(prefix ^ digit as u64);
+        index /= base;
+        digit_weight *= inverse_base;
+    }
+    value.min(1.0 - f64::EPSILON / 2.0)
+}
+
+/// The second dimension of the Sobol sequence, as a 32 bit fraction
+fn sobol_second(mut index: u32) -> u32 {
+    // Its direction numbers are each the previous one xor itself shifted right once
+    let mut direction = 1 << 31;
+    let mut value = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            value ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    value
+}
+
+/// Owen scramble the bits of a 32 bit fraction, each bit is flipped based on all the bits above it
+/// # Notes
+/// * Uses the hash by Vegdahl, an improvement on the Laine-Karras permutation
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x ^= x.wrapping_mul(0x3d20_adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x0552_6c56);
+    x ^= x.wrapping_mul(0x53a2_2864);
+    x.reverse_bits()
+}
+
+/// Find an element of a random permutation without building it
+/// # Arguments
+/// * 'i' - Index into the permutation, below 'length'
+/// * 'length' - Number of elements in the permutation
+/// * 'seed' - Picks the permutation
+/// # Notes
+/// * Kensler's "Correlated Multi-Jittered Sampling" permutation
+fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+    let p = seed;
+    let mut w = length.saturating_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | (p >> 27));
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    /// The first few values of every sample of a pixel, the 1D values are at 0 and 3
+    fn values(kind: SamplerKind, seed: u64, samples: u32) -> Vec<Vec<f64>> {
+        let mut sampler = kind.create(seed, samples);
+        (0..samples)
+            .map(|sample| {
+                sampler.start_sample(3, 7, sample);
+                let mut values = vec![sampler.get_1d()];
+                values.extend(sampler.get_2d());
+                values.push(sampler.bounce().choice);
+                values
+            })
+            .collect()
+    }
+
+    #[test]
+    fn one_1d_value_lands_in_each_cell() {
+        // The Sobol sequence is only evenly spread over powers of 2
+        for (kind, samples) in [
+            (SamplerKind::Stratified, 10),
+            (SamplerKind::Stratified, 16),
+            (SamplerKind::Sobol, 16),
+        ] {
+            let values = values(kind, 42, samples);
+            for dimension in [0, 3] {
+                let mut cells: Vec<u32> = values
+                    .iter()
+                    .map(|values| (values[dimension] * samples as f64) as u32)
+                    .collect();
+                cells.sort();
+                assert_eq!(
+                    cells,
+                    (0..samples).collect::<Vec<_>>(),
+                    "{:?} dimension {}",
+                    kind,
+                    dimension
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn values_repeat_for_the_same_seed() {
+        for kind in KINDS {
+            assert_eq!(values(kind, 7, 8), values(kind, 7, 8), "{:?}", kind);
+            assert_ne!(values(kind, 7, 8), values(kind, 8, 8), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn values_stay_below_1() {
+        for kind in KINDS {
+            for seed in 0..20 {
+                assert!(
+                    values(kind, seed, 33)
+                        .iter()
+                        .flatten()
+                        .all(|value| (0.0..1.0).contains(value)),
+                    "{:?}",
+                    kind
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,1164 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use thiserror::Error;
+use toml::{Table, Value};
+
+use crate::{
+    load_gltf, load_mesh, load_ply, load_stl,
+    material::{Diffuse, MaterialEnum, Metal, Pbr},
+    mesh::MeshError,
+    primitive::{cuboid, quad, uv_sphere},
+    vec3::{cross, unit_vector},
+    AdaptiveSampling, AoIntegrator, Background, CameraSettings, DrawingMode, Filter, FilterKind,
+    IdKind, Mesh, Node, NormalWeighting, RayTracer, RayTracerConfig, SamplerKind, Transform, Vec3,
+};
+
+/// A scene read from a scene file
+pub struct Scene {
+    /// Render settings, camera and background
+    pub config: RayTracerConfig,
+    /// Every mesh, primitive and light, placed in a scene graph
+    pub root: Node,
+}
+
+impl Scene {
+    /// Build a ray tracer which renders the scene
+    pub fn build(self) -> RayTracer {
+        let mut ray_tracer = self.config.build();
+        ray_tracer.add_node(&self.root);
+        ray_tracer
+    }
+
+    /// Count what the scene is made of, e.g. to check it before a long render
+    pub fn stats(&self) -> SceneStats {
+        let mut stats = SceneStats::default();
+        let mut meshes: HashSet<*const Mesh> = HashSet::new();
+        self.root.visit(&Transform::identity(), &mut |node, _| {
+            stats.nodes += 1;
+            if let Some(mesh) = &node.mesh {
+                stats.instances += 1;
+                stats.triangles += mesh.faces.len();
+
+                // Shared meshes only take up memory once
+                if meshes.insert(Arc::as_ptr(mesh)) {
+                    stats.meshes += 1;
+                    stats.vertices += mesh.positions.len();
+                }
+            }
+        });
+        stats
+    }
+}
+
+/// Counts describing a scene
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SceneStats {
+    /// Nodes in the scene graph, including the root
+    pub nodes: usize,
+    /// Placed meshes, which are rendered
+    pub instances: usize,
+    /// Distinct meshes, shared ones are counted once
+    pub meshes: usize,
+    /// Triangles rendered, counting each instance
+    pub triangles: usize,
+    /// Vertex positions stored by the distinct meshes
+    pub vertices: usize,
+}
+
+/// Errors produced while loading a scene file
+#[derive(Debug, Error)]
+pub enum SceneError {
+    #[error("Failed to read scene file {path}: {source}")]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse scene file {path}: {source}")]
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+
+    #[error("Invalid scene file {path} at '{key}': {reason}")]
+    Invalid {
+        path: String,
+        key: String,
+        reason: String,
+    },
+
+    #[error("Failed to load the mesh of '{key}' in scene file {path}: {source}")]
+    Mesh {
+        path: String,
+        key: String,
+        source: Box<MeshError>,
+    },
+}
+
+/// Load a TOML scene file
+/// # Arguments
+/// * 'path' - Path of the scene file
+/// # Returns
+/// * The render settings and scene graph
+/// # Notes
+/// * '[render]' holds 'width', 'height', 'max_depth', 'roulette_depth', 'mode' ("colors", "normals", "samples", "ao", "depth", "position", "uv", "barycentric", "faces", "objects" or "materials"), 'samples', 'sampler', 'filter' and 'filter_radius'
+/// * '[render.adaptive]' enables adaptive sampling, with 'max_samples' and 'noise_threshold'
+/// * '[render.ao]' holds the 'samples' and 'max_distance' of ambient occlusion, used by the "ao" mode and the ambient occlusion AOV
+/// * '[render.depth]' holds the 'near' and 'far' distances of the "depth" mode, which are fit to the scene when missing
+/// * '[camera]' holds 'position', 'look_at', 'up' and 'vertical_fov' in degrees
+/// * '[background]' holds either a single 'color' or a 'top' and 'bottom' gradient
+/// * '[materials.<name>]' define materials of 'type' "diffuse", "metal", "pbr" or "emissive"
+/// * '[[meshes]]' load a 'file' (OBJ, PLY, STL or glTF) relative to the scene file, each file is only loaded once
+/// * A mesh's 'smooth' is true to use the file's normals, or a table like { crease_angle = 30.0, weighting = "angle" } to generate them
+/// * '[[primitives]]' generate a "sphere", "cuboid" or "quad" mesh
+/// * '[[lights]]' are emissive spheres or quads, with a 'color' and 'intensity'
+/// * Meshes, primitives and lights may have a 'name', a 'material' name, a '[transform]' and the name of a 'parent' to be placed relative to
+/// * A transform applies 'scale', 'rotate' (degrees around x, y then z), 'axis' and 'angle', 'quaternion' and finally 'translate'
+/// * Unknown keys are errors, so typos don't silently fall back to defaults
+pub fn load_scene(path: &str) -> Result<Scene, SceneError> {
+    let text = fs::read_to_string(path).map_err(|source| SceneError::ReadFile {
+        path: path.to_string(),
+        source,
+    })?;
+    let table: Table = text.parse().map_err(|source| SceneError::Parse {
+        path: path.to_string(),
+        source,
+    })?;
+
+    let mut loader = Loader {
+        path,
+        directory: Path::new(path).parent().unwrap_or(Path::new("")),
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+        scenes: HashMap::new(),
+    };
+    let root = Section {
+        path,
+        key: String::new(),
+        table: &table,
+    };
+    root.check(&[
+        "render",
+        "camera",
+        "background",
+        "materials",
+        "meshes",
+        "primitives",
+        "lights",
+    ])?;
+
+    let mut config = RayTracerConfig::default();
+    if let Some(render) = root.section("render")? {
+        config = loader.render(&render, config)?;
+    }
+    if let Some(camera) = root.section("camera")? {
+        config = config.camera(loader.camera(&camera)?);
+    }
+    if let Some(background) = root.section("background")? {
+        config = config.background(loader.background(&background)?);
+    }
+
+    if let Some(materials) = root.section("materials")? {
+        for name in materials.table.keys() {
+            let material = materials
+                .section(name)?
+                .ok_or_else(|| materials.invalid(name, "expected a table".to_string()))?;
+            let material = loader.material(&material)?;
+            loader.materials.insert(name.clone(), Arc::new(material));
+        }
+    }
+
+    let mut entries: Vec<Entry> = Vec::new();
+    for mesh in root.sections("meshes")? {
+        entries.push(loader.mesh(&mesh)?);
+    }
+    for primitive in root.sections("primitives")? {
+        entries.push(loader.primitive(&primitive)?);
+    }
+    for light in root.sections("lights")? {
+        entries.push(loader.light(&light)?);
+    }
+
+    Ok(Scene {
+        config,
+        root: build_graph(path, entries)?,
+    })
+}
+
+/// A mesh, primitive or light waiting to be placed in the scene graph
+struct Entry {
+    /// Key of the entry, like "meshes[2]"
+    key: String,
+    name: String,
+    parent: Option<String>,
+    node: Node,
+}
+
+/// How a mesh file is shaded, read from its 'smooth' key
+/// * 'Flat' - One normal per face
+/// * 'File' - Smooth shaded with the normals in the file
+/// * 'Generated' - Smooth shaded with normals generated by Mesh::generate_normals, the crease angle is stored as its bits
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Shading {
+    Flat,
+    File,
+    Generated {
+        crease_angle: u64,
+        angle_weighted: bool,
+    },
+}
+
+/// State shared while reading a scene file
+struct Loader<'a> {
+    path: &'a str,
+    /// Directory of the scene file, mesh files are relative to it
+    directory: &'a Path,
+    /// Materials by name, shared by everything using them so they get one material ID
+    materials: HashMap<String, Arc<MaterialEnum>>,
+    /// Loaded mesh files by path and how they're shaded
+    meshes: HashMap<(PathBuf, Shading), Arc<Mesh>>,
+    /// Loaded glTF files by path
+    scenes: HashMap<PathBuf, Node>,
+}
+
+impl Loader<'_> {
+    /// Read the render settings on top of the defaults
+    fn render(
+        &self,
+        render: &Section,
+        mut config: RayTracerConfig,
+    ) -> Result<RayTracerConfig, SceneError> {
+        render.check(&[
+            "width",
+            "height",
+            "max_depth",
+            "roulette_depth",
+            "mode",
+            "samples",
+            "sampler",
+            "adaptive",
+            "filter",
+            "filter_radius",
+            "ao",
+            "depth",
+        ])?;
+
+        // Pixel positions are divided by the size minus 1
+        for (key, value) in [("width", &mut config.width), ("height", &mut config.height)] {
+            if let Some(size) = render.u32(key)? {
+                if size < 2 {
+                    return Err(render.invalid(key, "must be at least 2".to_string()));
+                }
+                *value = size;
+            }
+        }
+        if let Some(max_depth) = render.u32("max_depth")? {
+            config.max_depth = max_depth;
+        }
+        if let Some(roulette_depth) = render.u32("roulette_depth")? {
+            config.roulette_depth = roulette_depth;
+        }
+
+        config.sampler = match render.str("sampler")? {
+            Some("independent") => SamplerKind::Independent,
+            Some("stratified") => SamplerKind::Stratified,
+            Some("halton") => SamplerKind::Halton,
+            Some("sobol") => SamplerKind::Sobol,
+            Some(sampler) => {
+                return Err(render.invalid(
+                    "sampler",
+                    format!(
+                        "unknown sampler '{}', expected \"independent\", \"stratified\", \"halton\" or \"sobol\"",
+                        sampler
+                    ),
+                ))
+            }
+            None => config.sampler,
+        };
+
+        let mut filter = match render.str("filter")? {
+            Some("box") => Filter::new(FilterKind::Box),
+            Some("tent") => Filter::new(FilterKind::Tent),
+            Some("gaussian") => Filter::new(FilterKind::Gaussian),
+            Some("mitchell") => Filter::new(FilterKind::Mitchell),
+            Some("lanczos") => Filter::new(FilterKind::Lanczos),
+            Some(filter) => {
+                return Err(render.invalid(
+                    "filter",
+                    format!(
+                        "unknown filter '{}', expected \"box\", \"tent\", \"gaussian\", \"mitchell\" or \"lanczos\"",
+                        filter
+                    ),
+                ))
+            }
+            None => config.filter,
+        };
+        if let Some(radius) = render.f64("filter_radius")? {
+            if radius < 0.5 {
+                return Err(render.invalid("filter_radius", "must be at least 0.5".to_string()));
+            }
+            filter.radius = radius;
+        }
+        config = config.filter(filter);
+
+        if let Some(adaptive) = render.section("adaptive")? {
+            adaptive.check(&["max_samples", "noise_threshold"])?;
+            let default = AdaptiveSampling::default();
+            let max_samples = adaptive.u32("max_samples")?.unwrap_or(default.max_samples);
+            if max_samples == 0 {
+                return Err(adaptive.invalid("max_samples", "must be at least 1".to_string()));
+            }
+            config.adaptive = Some(AdaptiveSampling {
+                max_samples,
+                noise_threshold: adaptive
+                    .positive("noise_threshold")?
+                    .unwrap_or(default.noise_threshold),
+            });
+        }
+
+        let samples = render.u32("samples")?;
+        let mode = render.str("mode")?;
+        config.mode = match mode {
+            Some(
+                "colors" | "normals" | "depth" | "position" | "uv" | "barycentric" | "faces"
+                | "objects" | "materials",
+            )
+                if samples.is_some() =>
+            {
+                return Err(render.invalid(
+                    "samples",
+                    "only used when the mode is \"samples\" or \"ao\"".to_string(),
+                ))
+            }
+            Some("colors") => DrawingMode::Colors,
+            Some("normals") => DrawingMode::Normals,
+            Some("depth") => DrawingMode::Depth {
+                near: None,
+                far: None,
+            },
+            Some("position") => DrawingMode::Position,
+            Some("uv") => DrawingMode::Uv,
+            Some("barycentric") => DrawingMode::Barycentric,
+            Some("faces") => DrawingMode::FaceOrientation,
+            Some("objects") => DrawingMode::Ids(IdKind::Object),
+            Some("materials") => DrawingMode::Ids(IdKind::Material),
+            Some("samples") | Some("ao") | None => {
+                let samples = match samples {
+                    Some(0) => {
+                        return Err(render.invalid("samples", "must be at least 1".to_string()))
+                    }
+                    Some(samples) => samples,
+                    None => config.mode.samples().unwrap_or(1),
+                };
+                if mode == Some("ao") {
+                    DrawingMode::AmbientOcclusion(samples)
+                } else {
+                    DrawingMode::Samples(samples)
+                }
+            }
+            Some(mode) => {
+                return Err(render.invalid(
+                    "mode",
+                    format!(
+                    "unknown mode '{}', expected \"colors\", \"normals\", \"samples\", \"ao\", \"depth\", \"position\", \"uv\", \"barycentric\", \"faces\", \"objects\" or \"materials\"",
+                    mode
+                ),
+                ))
+            }
+        };
+
+        if config.adaptive.is_some() && config.mode.samples().is_none() {
+            return Err(render.invalid(
+                "adaptive",
+                "only used when the mode is \"samples\" or \"ao\"".to_string(),
+            ));
+        }
+
+        if let Some(ao) = render.section("ao")? {
+            ao.check(&["samples", "max_distance"])?;
+            let mut settings = AoIntegrator::default();
+            if let Some(samples) = ao.u32("samples")? {
+                if samples == 0 {
+                    return Err(ao.invalid("samples", "must be at least 1".to_string()));
+                }
+                settings.samples = samples;
+            }
+            if let Some(max_distance) = ao.positive("max_distance")? {
+                settings.max_distance = max_distance;
+            }
+            config = config.ambient_occlusion(settings);
+        }
+
+        if let Some(depth) = render.section("depth")? {
+            if mode != Some("depth") {
+                return Err(
+                    render.invalid("depth", "only used when the mode is \"depth\"".to_string())
+                );
+            }
+            depth.check(&["near", "far"])?;
+            let near = depth.f64("near")?;
+            if near.is_some_and(|near| near < 0.0) {
+                return Err(depth.invalid("near", "must be at least 0".to_string()));
+            }
+            let far = depth.positive("far")?;
+            if let (Some(near), Some(far)) = (near, far) {
+                if far <= near {
+                    return Err(depth.invalid("far", "must be greater than 'near'".to_string()));
+                }
+            }
+            config.mode = DrawingMode::Depth { near, far };
+        }
+
+        Ok(config)
+    }
+
+    /// Read the camera, missing keys keep the default camera's values
+    fn camera(&self, camera: &Section) -> Result<CameraSettings, SceneError> {
+        camera.check(&["position", "look_at", "up", "vertical_fov"])?;
+
+        let default = CameraSettings::default();
+        let settings = CameraSettings {
+            position: camera.vec3("position")?.unwrap_or(default.position),
+            look_at: camera.vec3("look_at")?.unwrap_or(default.look_at),
+            up: camera.vec3("up")?.unwrap_or(default.up),
+            vertical_fov: camera.f64("vertical_fov")?.unwrap_or(default.vertical_fov),
+        };
+
+        if !(settings.vertical_fov > 0.0 && settings.vertical_fov < 180.0) {
+            return Err(camera.invalid(
+                "vertical_fov",
+                "must be between 0 and 180 degrees".to_string(),
+            ));
+        }
+        if (settings.position - settings.look_at).near_zero() {
+            return Err(
+                camera.invalid("look_at", "must be different from the position".to_string())
+            );
+        }
+        // The camera's sideways axis is the cross product of 'up' and the view direction
+        let view = unit_vector(settings.look_at - settings.position);
+        if cross(settings.up, view).near_zero() {
+            return Err(camera.invalid(
+                "up",
+                "can't be zero or parallel to the view direction".to_string(),
+            ));
+        }
+
+        Ok(settings)
+    }
+
+    /// Read the background, either a single color or a gradient
+    fn background(&self, background: &Section) -> Result<Background, SceneError> {
+        background.check(&["color", "top", "bottom"])?;
+
+        let default = match Background::default() {
+            Background::Gradient { top, bottom } => (top, bottom),
+            Background::Color(color) => (color, color),
+        };
+        match (
+            background.vec3("color")?,
+            background.vec3("top")?,
+            background.vec3("bottom")?,
+        ) {
+            (Some(color), None, None) => Ok(Background::Color(color)),
+            (Some(_), _, _) => Err(background.invalid(
+                "color",
+                "can't be combined with a 'top' and 'bottom' gradient".to_string(),
+            )),
+            (None, top, bottom) => Ok(Background::Gradient {
+                top: top.unwrap_or(default.0),
+                bottom: bottom.unwrap_or(default.1),
+            }),
+        }
+    }
+
+    /// Read a material definition
+    fn material(&self, material: &Section) -> Result<MaterialEnum, SceneError> {
+        let kind = material
+            .str("type")?
+            .ok_or_else(|| material.invalid("type", "missing material type".to_string()))?;
+
+        match kind {
+            "diffuse" => {
+                material.check(&["type", "albedo"])?;
+                let albedo = material.vec3("albedo")?.unwrap_or(Vec3::new(0.5, 0.5, 0.5));
+                Ok(MaterialEnum::Diffuse(Diffuse::new(albedo)))
+            }
+            "metal" => {
+                material.check(&["type", "albedo", "smoothness"])?;
+                let albedo = material.vec3("albedo")?.unwrap_or(Vec3::new(0.5, 0.5, 0.5));
+                let smoothness = material.unit("smoothness")?.unwrap_or(0.0);
+                Ok(MaterialEnum::Metal(Metal::new(albedo, smoothness)))
+            }
+            "pbr" => {
+                material.check(&["type", "base_color", "metallic", "roughness", "emissive"])?;
+                let mut pbr = Pbr::new(
+                    material
+                        .vec3("base_color")?
+                        .unwrap_or(Vec3::new(1.0, 1.0, 1.0)),
+                    material.unit("metallic")?.unwrap_or(0.0),
+                    material.unit("roughness")?.unwrap_or(1.0),
+                );
+                if let Some(emissive) = material.vec3("emissive")? {
+                    pbr.emissive = emissive;
+                }
+                Ok(MaterialEnum::Pbr(pbr))
+            }
+            "emissive" => {
+                material.check(&["type", "color", "intensity"])?;
+                let color = material.vec3("color")?.unwrap_or(Vec3::new(1.0, 1.0, 1.0));
+                let intensity = material.non_negative("intensity")?.unwrap_or(1.0);
+                Ok(emissive(color * intensity))
+            }
+            kind => Err(material.invalid(
+                "type",
+                format!(
+                    "unknown material type '{}', expected \"diffuse\", \"metal\", \"pbr\" or \"emissive\"",
+                    kind
+                ),
+            )),
+        }
+    }
+
+    /// Read a mesh loaded from a file
+    fn mesh(&mut self, mesh: &Section) -> Result<Entry, SceneError> {
+        mesh.check(&["name", "parent", "material", "transform", "file", "smooth"])?;
+
+        let file = mesh
+            .str("file")?
+            .ok_or_else(|| mesh.invalid("file", "missing mesh file".to_string()))?;
+        let file_path = self.directory.join(file);
+        let extension = file_path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let smooth = self.shading(mesh)?;
+        let material = self.material_reference(mesh)?;
+        let load_error = |source| SceneError::Mesh {
+            path: self.path.to_string(),
+            key: mesh.key.clone(),
+            source: Box::new(source),
+        };
+
+        let mut node = match extension.as_str() {
+            "obj" | "ply" | "stl" => {
+                if extension == "stl" && smooth == Some(Shading::File) {
+                    return Err(mesh.invalid(
+                        "smooth",
+                        "STL files don't have vertex normals to smooth shade with".to_string(),
+                    ));
+                }
+                let shading = smooth.unwrap_or(Shading::Flat);
+                let key = (file_path.clone(), shading);
+                let shared = match self.meshes.get(&key) {
+                    Some(shared) => shared.clone(),
+                    None => {
+                        let file_path = file_path.to_string_lossy();
+                        let smooth = shading == Shading::File;
+                        let mut loaded = match extension.as_str() {
+                            "obj" => load_mesh(&file_path, smooth),
+                            "ply" => load_ply(&file_path, smooth),
+                            _ => load_stl(&file_path),
+                        }
+                        .map_err(load_error)?;
+                        if let Shading::Generated {
+                            crease_angle,
+                            angle_weighted,
+                        } = shading
+                        {
+                            let weighting = if angle_weighted {
+                                NormalWeighting::Angle
+                            } else {
+                                NormalWeighting::Area
+                            };
+                            loaded.generate_normals(f64::from_bits(crease_angle), weighting);
+                        }
+                        let shared = Arc::new(loaded);
+                        self.meshes.insert(key, shared.clone());
+                        shared
+                    }
+                };
+
+                let mut node = Node::new("").with_mesh(shared);
+                node.material = material;
+                node
+            }
+            "gltf" | "glb" => {
+                if smooth.is_some() {
+                    return Err(mesh.invalid(
+                        "smooth",
+                        "glTF files are shaded by their own normals".to_string(),
+                    ));
+                }
+                let mut node = match self.scenes.get(&file_path) {
+                    Some(node) => node.clone(),
+                    None => {
+                        let scene = load_gltf(&file_path.to_string_lossy()).map_err(load_error)?;
+                        self.scenes.insert(file_path.clone(), scene.root.clone());
+                        scene.root
+                    }
+                };
+
+                // The material replaces the materials of every mesh in the file
+                if let Some(material) = material {
+                    set_material(&mut node, &material);
+                }
+                node
+            }
+            _ => {
+                return Err(mesh.invalid(
+                    "file",
+                    format!(
+                        "unknown mesh format '{}', expected an OBJ, PLY, STL or glTF file",
+                        file
+                    ),
+                ))
+            }
+        };
+
+        node.transform = self.transform(mesh)?;
+        self.entry(mesh, node)
+    }
+
+    /// Read a generated mesh
+    fn primitive(&mut self, primitive: &Section) -> Result<Entry, SceneError> {
+        let common = ["name", "parent", "material", "transform", "type"];
+        let mesh = self.shape(primitive, &common, "primitive")?;
+
+        let mut node = Node::new("").with_mesh(Arc::new(mesh));
+        node.material = self.material_reference(primitive)?;
+        node.transform = self.transform(primitive)?;
+        self.entry(primitive, node)
+    }
+
+    /// Read a light, an emissive sphere or quad
+    fn light(&mut self, light: &Section) -> Result<Entry, SceneError> {
+        let common = ["name", "parent", "transform", "type", "color", "intensity"];
+        let mesh = self.shape(light, &common, "light")?;
+        let color = light.vec3("color")?.unwrap_or(Vec3::new(1.0, 1.0, 1.0));
+        let intensity = light.non_negative("intensity")?.unwrap_or(1.0);
+
+        let node = Node::new("")
+            .with_mesh(Arc::new(mesh))
+            .with_material(emissive(color * intensity))
+            .with_transform(self.transform(light)?);
+        self.entry(light, node)
+    }
+
+    /// Generate the mesh of a primitive or light from its 'type' and size
+    /// # Arguments
+    /// * 'section' - The primitive or light
+    /// * 'common' - Keys allowed for any type
+    /// * 'what' - Name of the section type, used in errors
+    fn shape(&self, section: &Section, common: &[&str], what: &str) -> Result<Mesh, SceneError> {
+        let kind = section
+            .str("type")?
+            .ok_or_else(|| section.invalid("type", format!("missing {} type", what)))?;
+        let allowed = |extra: &[&'static str]| [common, extra].concat();
+
+        match kind {
+            "sphere" => {
+                section.check(&allowed(&["radius", "segments", "rings"]))?;
+                let radius = section.positive("radius")?.unwrap_or(1.0);
+                let segments = section.u32("segments")?.unwrap_or(32);
+                let rings = section.u32("rings")?.unwrap_or(16);
+                if segments < 3 {
+                    return Err(section.invalid("segments", "must be at least 3".to_string()));
+                }
+                if rings < 2 {
+                    return Err(section.invalid("rings", "must be at least 2".to_string()));
+                }
+                Ok(uv_sphere(radius, segments, rings))
+            }
+            "cuboid" if what == "primitive" => {
+                section.check(&allowed(&["size"]))?;
+                let size = section.scale("size")?.unwrap_or(Vec3::new(1.0, 1.0, 1.0));
+                Ok(cuboid(size))
+            }
+            "quad" => {
+                section.check(&allowed(&["width", "depth"]))?;
+                let width = section.positive("width")?.unwrap_or(1.0);
+                let depth = section.positive("depth")?.unwrap_or(1.0);
+                Ok(quad(width, depth))
+            }
+            kind => {
+                let expected = if what == "primitive" {
+                    "\"sphere\", \"cuboid\" or \"quad\""
+                } else {
+                    "\"sphere\" or \"quad\""
+                };
+                Err(section.invalid(
+                    "type",
+                    format!("unknown {} type '{}', expected {}", what, kind, expected),
+                ))
+            }
+        }
+    }
+
+    /// Read how a mesh file is shaded
+    /// # Returns
+    /// * None without a 'smooth' key, which is true, false or a table with the 'crease_angle' and 'weighting' of generated normals
+    fn shading(&self, mesh: &Section) -> Result<Option<Shading>, SceneError> {
+        if let Some(Value::Table(_)) = mesh.table.get("smooth") {
+            let Some(smooth) = mesh.section("smooth")? else {
+                return Ok(None);
+            };
+            smooth.check(&["crease_angle", "weighting"])?;
+            let crease_angle = smooth.f64("crease_angle")?.unwrap_or(30.0);
+            if !(0.0..=180.0).contains(&crease_angle) {
+                return Err(smooth.invalid(
+                    "crease_angle",
+                    "must be between 0 and 180 degrees".to_string(),
+                ));
+            }
+            let angle_weighted = match smooth.str("weighting")? {
+                Some("angle") | None => true,
+                Some("area") => false,
+                Some(weighting) => {
+                    return Err(smooth.invalid(
+                        "weighting",
+                        format!(
+                            "unknown weighting '{}', expected \"angle\" or \"area\"",
+                            weighting
+                        ),
+                    ))
+                }
+            };
+            return Ok(Some(Shading::Generated {
+                crease_angle: crease_angle.to_bits(),
+                angle_weighted,
+            }));
+        }
+
+        Ok(mesh
+            .bool("smooth")?
+            .map(|smooth| if smooth { Shading::File } else { Shading::Flat }))
+    }
+
+    /// Look up the material named by a section's 'material' key
+    fn material_reference(
+        &self,
+        section: &Section,
+    ) -> Result<Option<Arc<MaterialEnum>>, SceneError> {
+        match section.str("material")? {
+            Some(name) => match self.materials.get(name) {
+                Some(material) => Ok(Some(material.clone())),
+                None => Err(section.invalid(
+                    "material",
+                    format!("no material named '{}' in [materials]", name),
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Read a section's '[transform]', identity if it has none
+    fn transform(&self, section: &Section) -> Result<Transform, SceneError> {
+        let Some(transform) = section.section("transform")? else {
+            return Ok(Transform::identity());
+        };
+        transform.check(&[
+            "scale",
+            "rotate",
+            "axis",
+            "angle",
+            "quaternion",
+            "translate",
+        ])?;
+
+        let mut result = Transform::identity();
+        if let Some(scale) = transform.scale("scale")? {
+            result = result.then(&Transform::scale(scale));
+        }
+        if let Some(rotate) = transform.vec3("rotate")? {
+            result = result.then(&Transform::rotate_euler(rotate));
+        }
+        match (transform.vec3("axis")?, transform.f64("angle")?) {
+            (Some(axis), Some(angle)) => {
+                if axis.near_zero() {
+                    return Err(transform.invalid("axis", "can't be zero".to_string()));
+                }
+                result = result.then(&Transform::rotate(axis, angle));
+            }
+            (Some(_), None) => {
+                return Err(transform.invalid("angle", "missing angle for the axis".to_string()))
+            }
+            (None, Some(_)) => {
+                return Err(transform.invalid("axis", "missing axis for the angle".to_string()))
+            }
+            (None, None) => {}
+        }
+        if let Some(quaternion) = transform.numbers::<4>("quaternion")? {
+            if quaternion.iter().all(|v| *v == 0.0) {
+                return Err(transform.invalid("quaternion", "can't be zero".to_string()));
+            }
+            result = result.then(&Transform::rotate_quaternion(quaternion));
+        }
+        if let Some(translate) = transform.vec3("translate")? {
+            result = result.then(&Transform::translate(translate));
+        }
+
+        Ok(result)
+    }
+
+    /// Read the name and parent of a mesh, primitive or light
+    fn entry(&self, section: &Section, mut node: Node) -> Result<Entry, SceneError> {
+        let name = section
+            .str("name")?
+            .map(str::to_string)
+            .unwrap_or_else(|| section.key.clone());
+        node.name = name.clone();
+
+        Ok(Entry {
+            key: section.key.clone(),
+            name,
+            parent: section.str("parent")?.map(str::to_string),
+            node,
+        })
+    }
+}
+
+/// Create a material which only gives off light
+fn emissive(color: Vec3) -> MaterialEnum {
+    let mut material = Pbr::new(Vec3::new(0.0, 0.0, 0.0), 0.0, 1.0);
+    material.emissive = color;
+    MaterialEnum::Pbr(material)
+}
+
+/// Replace the material of every mesh in a graph
+fn set_material(node: &mut Node, material: &Arc<MaterialEnum>) {
+    if node.mesh.is_some() {
+        node.material = Some(material.clone());
+    }
+    for child in node.children.iter_mut() {
+        set_material(child, material);
+    }
+}
+
+/// Place every entry below its parent, entries without a parent go below the root
+fn build_graph(path: &str, entries: Vec<Entry>) -> Result<Node, SceneError> {
+    let invalid = |entry: &Entry, key: &str, reason: String| SceneError::Invalid {
+        path: path.to_string(),
+        key: format!("{}.{}", entry.key, key),
+        reason,
+    };
+
+    let mut names: HashSet<&str> = HashSet::new();
+    for entry in entries.iter() {
+        if !names.insert(&entry.name) {
+            return Err(invalid(
+                entry,
+                "name",
+                format!("another mesh, primitive or light is named '{}'", entry.name),
+            ));
+        }
+    }
+    for entry in entries.iter() {
+        if let Some(parent) = &entry.parent {
+            if !names.contains(parent.as_str()) {
+                return Err(invalid(
+                    entry,
+                    "parent",
+                    format!("no mesh, primitive or light is named '{}'", parent),
+                ));
+            }
+        }
+    }
+
+    /// Take the entries below a parent out of the list, with all of their descendants
+    fn children(parent: Option<&str>, entries: &mut [Option<Entry>]) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        for index in 0..entries.len() {
+            let is_child =
+                matches!(&entries[index], Some(entry) if entry.parent.as_deref() == parent);
+            if let Some(entry) = is_child.then(|| entries[index].take()).flatten() {
+                let mut node = entry.node;
+                node.children.extend(children(Some(&entry.name), entries));
+                nodes.push(node);
+            }
+        }
+        nodes
+    }
+
+    let mut remaining: Vec<Option<Entry>> = entries.into_iter().map(Some).collect();
+    let mut root = Node::new("root");
+    root.children = children(None, &mut remaining);
+
+    // Anything left over has a parent which is one of its own descendants
+    if let Some(entry) = remaining.into_iter().flatten().next() {
+        return Err(invalid(
+            &entry,
+            "parent",
+            "the parents form a cycle".to_string(),
+        ));
+    }
+
+    Ok(root)
+}
+
+/// A table of the scene file and the key it's stored at, used to report errors
+struct Section<'a> {
+    path: &'a str,
+    /// Key path of the table, like "meshes[2].transform", empty for the root
+    key: String,
+    table: &'a Table,
+}
+
+impl<'a> Section<'a> {
+    /// The full key path of a key in this table
+    fn key(&self, key: &str) -> String {
+        if self.key.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.key, key)
+        }
+    }
+
+    /// Create an error for a key in this table
+    fn invalid(&self, key: &str, reason: String) -> SceneError {
+        SceneError::Invalid {
+            path: self.path.to_string(),
+            key: self.key(key),
+            reason,
+        }
+    }
+
+    /// Check that the table only uses the allowed keys
+    fn check(&self, allowed: &[&str]) -> Result<(), SceneError> {
+        match self
+            .table
+            .keys()
+            .find(|key| !allowed.contains(&key.as_str()))
+        {
+            Some(key) => Err(self.invalid(
+                key,
+                format!("unknown key, expected one of: {}", allowed.join(", ")),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Read a sub-table
+    fn section(&self, key: &str) -> Result<Option<Section<'a>>, SceneError> {
+        match self.table.get(key) {
+            Some(Value::Table(table)) => Ok(Some(Section {
+                path: self.path,
+                key: self.key(key),
+                table,
+            })),
+            Some(_) => Err(self.invalid(key, "expected a table".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Read an array of tables, like '[[meshes]]'
+    fn sections(&self, key: &str) -> Result<Vec<Section<'a>>, SceneError> {
+        let array = match self.table.get(key) {
+            Some(Value::Array(array)) => array,
+            Some(_) => return Err(self.invalid(key, "expected an array of tables".to_string())),
+            None => return Ok(Vec::new()),
+        };
+
+        array
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let key = format!("{}[{}]", key, index);
+                match value {
+                    Value::Table(table) => Ok(Section {
+                        path: self.path,
+                        key: self.key(&key),
+                        table,
+                    }),
+                    _ => Err(self.invalid(&key, "expected a table".to_string())),
+                }
+            })
+            .collect()
+    }
+
+    /// Read a string
+    fn str(&self, key: &str) -> Result<Option<&'a str>, SceneError> {
+        match self.table.get(key) {
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(self.invalid(key, "expected a string".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Read a boolean
+    fn bool(&self, key: &str) -> Result<Option<bool>, SceneError> {
+        match self.table.get(key) {
+            Some(Value::Boolean(value)) => Ok(Some(*value)),
+            Some(_) => Err(self.invalid(key, "expected true or false".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Read a number, integers are accepted as well
+    fn f64(&self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.table.get(key) {
+            Some(value) => number(value)
+                .map(Some)
+                .ok_or_else(|| self.invalid(key, "expected a number".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Read a number which must be greater than 0
+    fn positive(&self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.f64(key)? {
+            Some(value) if value <= 0.0 => {
+                Err(self.invalid(key, "must be greater than 0".to_string()))
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Read a number which must be 0 or greater
+    fn non_negative(&self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.f64(key)? {
+            Some(value) if value.is_nan() || value < 0.0 => {
+                Err(self.invalid(key, "must be 0 or greater".to_string()))
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Read a number which must be between 0 and 1
+    fn unit(&self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.f64(key)? {
+            Some(value) if !(0.0..=1.0).contains(&value) => {
+                Err(self.invalid(key, "must be between 0 and 1".to_string()))
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Read a non-negative integer
+    fn u32(&self, key: &str) -> Result<Option<u32>, SceneError> {
+        match self.table.get(key) {
+            Some(Value::Integer(value)) => u32::try_from(*value)
+                .map(Some)
+                .map_err(|_| self.invalid(key, format!("{} is out of range", value))),
+            Some(_) => Err(self.invalid(key, "expected a whole number".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Read an array of exactly N numbers
+    fn numbers<const N: usize>(&self, key: &str) -> Result<Option<[f64; N]>, SceneError> {
+        let error = || self.invalid(key, format!("expected an array of {} numbers", N));
+        match self.table.get(key) {
+            Some(Value::Array(array)) if array.len() == N => {
+                let mut result = [0.0; N];
+                for (value, item) in result.iter_mut().zip(array.iter()) {
+                    *value = number(item).ok_or_else(error)?;
+                }
+                Ok(Some(result))
+            }
+            Some(_) => Err(error()),
+            None => Ok(None),
+        }
+    }
+
+    /// Read an [x, y, z] array
+    fn vec3(&self, key: &str) -> Result<Option<Vec3>, SceneError> {
+        Ok(self.numbers::<3>(key)?.map(|[x, y, z]| Vec3::new(x, y, z)))
+    }
+
+    /// Read a scale, either one number for every axis or an [x, y, z] array, none of them 0
+    fn scale(&self, key: &str) -> Result<Option<Vec3>, SceneError> {
+        let scale = match self.table.get(key) {
+            Some(Value::Array(_)) => self.vec3(key)?,
+            Some(value) => {
+                let s = number(value).ok_or_else(|| {
+                    self.invalid(
+                        key,
+                        "expected a number or an array of 3 numbers".to_string(),
+                    )
+                })?;
+                Some(Vec3::new(s, s, s))
+            }
+            None => None,
+        };
+
+        match scale {
+            Some(s) if s.x == 0.0 || s.y == 0.0 || s.z == 0.0 => {
+                Err(self.invalid(key, "can't be 0".to_string()))
+            }
+            scale => Ok(scale),
+        }
+    }
+}
+
+/// Convert an integer or float value to a number
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(value) => Some(*value as f64),
+        Value::Float(value) => Some(*value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// Load a scene file written to the temp directory
+    fn load(name: &str, contents: &str) -> Result<Scene, SceneError> {
+        let dir = TempDir::new(&format!("scene_{}", name));
+        load_scene(&dir.write("scene.toml", contents))
+    }
+
+    #[test]
+    fn camera_up_parallel_to_the_view_is_an_error() {
+        let camera = "[camera]\nposition = [0, 5, 0]\nlook_at = [0, 0, 0]\n";
+        let error = load("up.toml", &format!("{}up = [0, 1, 0]\n", camera))
+            .err()
+            .expect("up is parallel to the view direction");
+        assert!(matches!(error, SceneError::Invalid { ref key, .. } if key.ends_with("up")));
+
+        assert!(load("up_zero.toml", &format!("{}up = [0, 0, 0]\n", camera)).is_err());
+        assert!(load("up_ok.toml", &format!("{}up = [0, 0, 1]\n", camera)).is_ok());
+    }
+
+    #[test]
+    fn ao_settings_are_read_in_any_mode() {
+        let scene = load(
+            "ao_colors",
+            "[render]\nmode = \"colors\"\n[render.ao]\nsamples = 9\nmax_distance = 2.5\n",
+        )
+        .unwrap();
+        let ao = scene.config.ambient_occlusion_settings();
+        assert_eq!(ao.samples, 9);
+        assert_eq!(ao.max_distance, 2.5);
+    }
+
+    #[test]
+    fn material_numbers_out_of_range_are_errors() {
+        let invalid_key = |name: &str, material: &str| match load(
+            name,
+            &format!("[materials.test]\n{}\n", material),
+        ) {
+            Err(SceneError::Invalid { key, .. }) => key,
+            other => panic!("expected an invalid key, got {:?}", other.map(|_| ())),
+        };
+
+        let rough = invalid_key("smooth_high", "type = \"metal\"\nsmoothness = 1.5");
+        assert!(rough.ends_with("smoothness"));
+        let rough = invalid_key("smooth_low", "type = \"metal\"\nsmoothness = -0.1");
+        assert!(rough.ends_with("smoothness"));
+        let dark = invalid_key("negative", "type = \"emissive\"\nintensity = -1");
+        assert!(dark.ends_with("intensity"));
+        let nan = invalid_key("nan", "type = \"emissive\"\nintensity = nan");
+        assert!(nan.ends_with("intensity"));
+
+        assert!(load("ok", "[materials.a]\ntype = \"metal\"\nsmoothness = 1\n[materials.b]\ntype = \"emissive\"\nintensity = 0").is_ok());
+    }
+}
@@ -0,0 +1,228 @@
+use std::{fs::File, io::Read};
+
+use std::collections::HashMap;
+
+use crate::{
+    mesh::{Face, MeshError},
+    triangle::geometric_normal,
+    Mesh, Vec3,
+};
+
+/// Size of the binary header and triangle count
+const BINARY_HEADER_SIZE: usize = 84;
+/// Size of one binary triangle: normal, 3 points and a 2 byte attribute
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+/// Load an STL mesh
+/// # Arguments
+/// * 'path' - Path of an STL file, ASCII or binary
+/// # Returns
+/// * A flat shaded mesh and all of its triangles, including a default material
+/// # Notes
+/// * Binary files are recognized by their size, since they may also start with 'solid'
+/// * Facets with a zero normal use the geometric normal
+pub fn load_stl(path: &str) -> Result<Mesh, MeshError> {
+    let mut file = File::open(path).map_err(|source| MeshError::OpenFile {
+        path: path.to_string(),
+        source,
+    })?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)
+        .map_err(|source| MeshError::ReadFile {
+            path: path.to_string(),
+            source,
+        })?;
+
+    let binary_size = data.get(80..BINARY_HEADER_SIZE).map(|count| {
+        let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+        BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE
+    });
+
+    let triangles = if binary_size == Some(data.len()) || !data.starts_with(b"solid") {
+        parse_binary(path, &data)?
+    } else {
+        parse_ascii(path, &data)?
+    };
+
+    // Return the new mesh based on the facets
+    Ok(build_mesh(triangles))
+}
+
+/// A facet as written in the file, its points and normal
+type Facet = ([Vec3; 3], Vec3);
+
+/// Create a mesh from facets, sharing points which are exactly equal
+/// # Notes
+/// * Facets with a zero normal use the geometric normal
+fn build_mesh(facets: Vec<Facet>) -> Mesh {
+    let mut mesh = Mesh::new_mesh(Vec::new());
+    let mut indices: HashMap<[u64; 3], usize> = HashMap::new();
+
+    for (points, normal) in facets {
+        let positions = points.map(|p| {
+            *indices
+                .entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()])
+                .or_insert_with(|| {
+                    mesh.positions.push(p);
+                    mesh.positions.len() - 1
+                })
+        });
+
+        mesh.normals.push(if normal.near_zero() {
+            geometric_normal(points)
+        } else {
+            normal
+        });
+        mesh.faces
+            .push(Face::new(positions, mesh.normals.len() - 1));
+    }
+
+    mesh
+}
+
+/// Parse the facets of a binary STL file
+fn parse_binary(path: &str, data: &[u8]) -> Result<Vec<Facet>, MeshError> {
+    let invalid = |reason: String| MeshError::Invalid {
+        path: path.to_string(),
+        reason,
+    };
+
+    let count = data
+        .get(80..BINARY_HEADER_SIZE)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize)
+        .ok_or_else(|| invalid("file is too short for a binary STL header".to_string()))?;
+
+    let body = &data[BINARY_HEADER_SIZE..];
+    if body.len() < count * BINARY_TRIANGLE_SIZE {
+        return Err(invalid(format!(
+            "header declares {} triangles but the file only holds {}",
+            count,
+            body.len() / BINARY_TRIANGLE_SIZE
+        )));
+    }
+
+    let triangles = body
+        .chunks_exact(BINARY_TRIANGLE_SIZE)
+        .take(count)
+        .map(|chunk| {
+            let vec3 = |offset: usize| {
+                let float = |i: usize| {
+                    let b = &chunk[offset + i * 4..offset + i * 4 + 4];
+                    f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64
+                };
+                Vec3::new(float(0), float(1), float(2))
+            };
+            ([vec3(12), vec3(24), vec3(36)], vec3(0))
+        })
+        .collect();
+
+    Ok(triangles)
+}
+
+/// Parse the facets of an ASCII STL file
+fn parse_ascii(path: &str, data: &[u8]) -> Result<Vec<Facet>, MeshError> {
+    let text = String::from_utf8_lossy(data);
+
+    let mut triangles: Vec<Facet> = Vec::new();
+    let mut normal = Vec3::new(0.0, 0.0, 0.0);
+    let mut points: Vec<Vec3> = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let parse_error = |reason: String| MeshError::Parse {
+            path: path.to_string(),
+            line: index + 1,
+            reason,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let vec3 = |words: &[&str], what: &str| -> Result<Vec3, MeshError> {
+            let float = |i: usize| {
+                let word = words
+                    .get(i)
+                    .ok_or_else(|| parse_error(format!("missing {} component", what)))?;
+                word.parse::<f64>()
+                    .map_err(|_| parse_error(format!("invalid {} component '{}'", what, word)))
+            };
+            Ok(Vec3::new(float(0)?, float(1)?, float(2)?))
+        };
+
+        match words.first() {
+            // facet normal nx ny nz
+            Some(&"facet") => {
+                normal = vec3(words.get(2..).unwrap_or_default(), "normal")?;
+                points.clear();
+            }
+            Some(&"vertex") => points.push(vec3(&words[1..], "vertex")?),
+            Some(&"endfacet") => {
+                if points.len() < 3 {
+                    return Err(parse_error(format!(
+                        "facet has {} vertices, expected at least 3",
+                        points.len()
+                    )));
+                }
+
+                // Triangulate as a fan around the first vertex
+                for i in 1..points.len() - 1 {
+                    triangles.push(([points[0], points[i], points[i + 1]], normal));
+                }
+            }
+            // solid, outer loop, endloop and endsolid only structure the file
+            _ => {}
+        }
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const POINTS: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    fn ascii() -> Vec<u8> {
+        let mut text = "solid test\nfacet normal 0 0 1\nouter loop\n".to_string();
+        for p in POINTS {
+            text += &format!("vertex {} {} {}\n", p[0], p[1], p[2]);
+        }
+        text += "endloop\nendfacet\nendsolid test\n";
+        text.into_bytes()
+    }
+
+    /// A binary file, whose 80 byte header may start with 'solid' like an ASCII file
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let mut data = header.to_vec();
+        data.resize(80, b' ');
+        data.extend(1_u32.to_le_bytes());
+        for v in [[0.0, 0.0, 1.0]].iter().chain(POINTS.iter()).flatten() {
+            data.extend(v.to_le_bytes());
+        }
+        data.extend([0, 0]);
+        data
+    }
+
+    fn load(name: &str, data: &[u8]) -> Mesh {
+        let dir = TempDir::new(&format!("stl_{}", name));
+        load_stl(&dir.write("mesh.stl", data)).unwrap()
+    }
+
+    #[test]
+    fn ascii_and_binary_are_detected() {
+        for (name, data) in [
+            ("ascii", ascii()),
+            ("binary", binary(b"binary")),
+            ("binary_solid", binary(b"solid but binary")),
+        ] {
+            let mesh = load(name, &data);
+            assert_eq!(mesh.faces.len(), 1, "{}", name);
+            let trig = mesh.triangle(0);
+            let points = trig.points.map(|p| [p.x, p.y, p.z]);
+            assert_eq!(points, POINTS.map(|p| p.map(f64::from)), "{}", name);
+            assert_eq!(
+                [trig.normal.x, trig.normal.y, trig.normal.z],
+                [0.0, 0.0, 1.0]
+            );
+        }
+    }
+}
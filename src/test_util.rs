@@ -0,0 +1,37 @@
+use std::{fs, path::PathBuf};
+
+/// A directory in the system's temp directory for a test's files
+/// # Notes
+/// * The directory and everything in it is removed when it's dropped, also when an assertion fails
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create an empty directory
+    /// # Arguments
+    /// * 'name' - Name which no other test running at the same time uses
+    pub(crate) fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("rust_raytracer_{}_{}", std::process::id(), name));
+        // Clear what an earlier run which was killed may have left
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Write a file into the directory
+    /// # Returns
+    /// * The path of the file, to pass to a loader
+    pub(crate) fn write(&self, file: &str, contents: impl AsRef<[u8]>) -> String {
+        let path = self.path.join(file);
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,133 @@
+use thiserror::Error;
+
+use crate::Vec3;
+
+/// An RGB image which materials can look colors up in
+#[derive(Clone, Debug)]
+pub struct Texture {
+    /// Width in pixels
+    width: u32,
+    /// Height in pixels
+    height: u32,
+    /// Linear r,g,b values in the x,y,z positions, row by row starting at the top of the image
+    pixels: Vec<Vec3>,
+}
+
+impl Texture {
+    /// Create a new texture
+    /// # Arguments
+    /// * 'width, height' - Size of the image in pixels
+    /// * 'pixels' - width * height colors, row by row starting at the top of the image
+    /// # Returns
+    /// * TextureError::SizeMismatch if there aren't width * height pixels
+    pub fn new(width: u32, height: u32, pixels: Vec<Vec3>) -> Result<Self, TextureError> {
+        if pixels.len() as u64 != width as u64 * height as u64 {
+            return Err(TextureError::SizeMismatch {
+                width,
+                height,
+                pixels: pixels.len(),
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Width in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Linear r,g,b values in the x,y,z positions, row by row starting at the top of the image
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    /// Look up a color with bilinear filtering, repeating the image outside 0-1
+    /// # Arguments
+    /// * 'uv' - Texture coordinates, (0,0) is the bottom left of the image
+    /// # Returns
+    /// * The interpolated color
+    pub fn sample(&self, uv: [f64; 2]) -> Vec3 {
+        if self.pixels.is_empty() {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+
+        // Pixel centers are at half coordinates, and rows are stored top to bottom
+        let x = uv[0] * self.width as f64 - 0.5;
+        let y = (1.0 - uv[1]) * self.height as f64 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+
+        let texel = |x: f64, y: f64| {
+            let x = (x as i64).rem_euclid(self.width as i64) as usize;
+            let y = (y as i64).rem_euclid(self.height as i64) as usize;
+            self.pixels[y * self.width as usize + x]
+        };
+
+        let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1.0, y0) * tx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - tx) + texel(x0 + 1.0, y0 + 1.0) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+/// Convert an sRGB encoded color channel in the range 0-1 to linear
+pub(crate) fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear color channel in the range 0-1 to sRGB encoding
+pub(crate) fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Errors produced while creating a texture
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error("Texture of {width}x{height} pixels was given {pixels} pixels")]
+    SizeMismatch {
+        width: u32,
+        height: u32,
+        pixels: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_rejects_wrong_pixel_count() {
+        let pixels = vec![Vec3::new(1.0, 0.0, 0.0); 3];
+        assert!(matches!(
+            Texture::new(2, 2, pixels),
+            Err(TextureError::SizeMismatch { pixels: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn sample_hits_pixel_centers() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        let texture = Texture::new(2, 1, vec![red, blue]).unwrap();
+        assert_eq!(texture.sample([0.25, 0.5]).x, 1.0);
+        assert_eq!(texture.sample([0.75, 0.5]).z, 1.0);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{config::TileOrder, Region, Result};
+
+/// A finished part of the image
+pub struct Tile {
+    /// Where the tile is, in pixels from the top left corner of the rendered image
+    pub area: Region,
+    /// 8 bit r,g,b values, row by row starting at the top left of the tile
+    pub pixels: Vec<[u8; 3]>,
+}
+
+/// Receives tiles as they are finished, e.g. a framebuffer or a file being written
+/// # Notes
+/// * Tiles arrive one at a time on the thread which started the render, in the order they finish
+pub trait TileSink {
+    /// Take a finished tile, an error stops the render
+    fn write_tile(&mut self, tile: &Tile) -> Result<()>;
+}
+
+impl<F: FnMut(&Tile) -> Result<()>> TileSink for F {
+    fn write_tile(&mut self, tile: &Tile) -> Result<()> {
+        self(tile)
+    }
+}
+
+/// Split an image into tiles
+/// # Arguments
+/// * 'width, height' - Size of the image in pixels
+/// * 'size' - Width and height of the tiles, the ones at the right and bottom edges may be smaller
+/// * 'order' - Order the tiles are returned in
+/// # Returns
+/// * The area of each tile, in pixels from the top left corner
+pub(crate) fn tiles(width: u32, height: u32, size: u32, order: TileOrder) -> Vec<Region> {
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+    if columns == 0 || rows == 0 {
+        return Vec::new();
+    }
+
+    let cells: Vec<(u32, u32)> = match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => {
+            // Walk a square curve covering the grid and skip the cells outside of it
+            let n = columns.max(rows).next_power_of_two();
+            (0..n * n)
+                .map(|d| hilbert(n, d))
+                .filter(|&(column, row)| column < columns && row < rows)
+                .collect()
+        }
+    };
+
+    cells
+        .into_iter()
+        .map(|(column, row)| {
+            let x = column * size;
+            let y = row * size;
+            Region {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+/// Walk a grid in a square spiral starting at its center
+/// # Returns
+/// * Every (column, row) of the grid once
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = (columns * rows) as usize;
+    let mut cells = Vec::with_capacity(total);
+    let (mut column, mut row) = (((columns - 1) / 2) as i64, ((rows - 1) / 2) as i64);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+    // Legs grow by one every second turn, 1, 1, 2, 2, 3, 3, ...
+    let mut leg = 0;
+    while cells.len() < total {
+        let (dx, dy) = directions[leg % 4];
+        for _ in 0..leg / 2 + 1 {
+            if (0..columns as i64).contains(&column) && (0..rows as i64).contains(&row) {
+                cells.push((column as u32, row as u32));
+            }
+            column += dx;
+            row += dy;
+        }
+        leg += 1;
+    }
+
+    cells
+}
+
+/// Find the cell at a distance along a Hilbert curve
+/// # Arguments
+/// * 'n' - Width and height of the square the curve fills, a power of two
+/// * 'd' - Distance along the curve
+/// # Returns
+/// * The (column, row) of the cell
+fn hilbert(n: u32, d: u32) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < n {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+
+        // Rotate the quadrant so the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        let sizes = [
+            (64, 64, 16),
+            (100, 37, 16),
+            (5, 90, 7),
+            (1, 1, 32),
+            (33, 33, 1),
+        ];
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            for (width, height, size) in sizes {
+                let mut covered = vec![0; (width * height) as usize];
+                for tile in tiles(width, height, size, order) {
+                    assert!(tile.width > 0 && tile.height > 0);
+                    assert!(tile.x + tile.width <= width && tile.y + tile.height <= height);
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            covered[(y * width + x) as usize] += 1;
+                        }
+                    }
+                }
+                assert!(
+                    covered.iter().all(|&count| count == 1),
+                    "{:?} tiles of {}x{} with size {} don't cover every pixel once",
+                    order,
+                    width,
+                    height,
+                    size
+                );
+            }
+        }
+        assert!(tiles(0, 10, 16, TileOrder::Hilbert).is_empty());
+    }
+}
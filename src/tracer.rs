@@ -1,16 +1,41 @@
-use rand::Rng;
 use rayon::prelude::*;
-use std::{io::Write, sync::Arc};
+use std::{
+    io::Write,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Instant,
+};
 
 use crate::{
-    vec3::{barycentric, unit_vector},
-    Camera, DrawingMode, Material, Mesh, Ray, RayTracerConfig, Result, Vec3, World,
+    config::{IdKind, Region},
+    error::Error,
+    instance::Aabb,
+    integrator::{
+        BarycentricIntegrator, ColorsIntegrator, DepthIntegrator, FaceOrientationIntegrator,
+        IdIntegrator, Integrator, NormalsIntegrator, PathIntegrator, PositionIntegrator, SceneView,
+        UvIntegrator,
+    },
+    progress::{Budget, Pass, ProgressTracker},
+    sampler::Sampler,
+    tile::{self, Tile, TileSink},
+    Camera, DrawingMode, IdBuffer, Image, ImageFormat, ImageWriter, Instance, Mesh, Node, Ray,
+    RayTracerConfig, Result, Vec3, World,
 };
 
 pub struct RayTracer {
     camera: Camera,
     config: RayTracerConfig,
+    /// Calculates the color of each sample
+    integrator: Arc<dyn Integrator>,
+    /// Part of the image which is rendered, clipped to the image
+    region: Region,
     world: World,
+    /// Box around everything added to the world, None while it's empty
+    bounds: Option<Aabb>,
 }
 
 impl Default for RayTracer {
@@ -22,180 +47,647 @@ impl Default for RayTracer {
 impl RayTracer {
     pub(crate) fn new(config: RayTracerConfig) -> RayTracer {
         let aspect_ratio: f64 = (config.width as f64) / (config.height as f64);
+
+        // Clip the region to the image, an empty region renders nothing
+        let full = Region {
+            x: 0,
+            y: 0,
+            width: config.width,
+            height: config.height,
+        };
+        let region = config.region.map_or(full, |r| {
+            let x = r.x.min(config.width);
+            let y = r.y.min(config.height);
+            Region {
+                x,
+                y,
+                width: r.width.min(config.width - x),
+                height: r.height.min(config.height - y),
+            }
+        });
+
+        // A custom integrator replaces the one of the drawing mode
+        let integrator: Arc<dyn Integrator> = match (&config.integrator, config.mode) {
+            (Some(integrator), _) => integrator.clone(),
+            (None, DrawingMode::Colors) => Arc::new(ColorsIntegrator),
+            (None, DrawingMode::Normals) => Arc::new(NormalsIntegrator),
+            (None, DrawingMode::Samples(_)) => Arc::new(PathIntegrator {
+                max_depth: config.max_depth,
+                roulette_depth: config.roulette_depth,
+            }),
+            (None, DrawingMode::AmbientOcclusion(_)) => Arc::new(config.ambient_occlusion),
+            (None, DrawingMode::Depth { near, far }) => Arc::new(DepthIntegrator {
+                near,
+                far,
+                forward: config.camera.look_at - config.camera.position,
+            }),
+            (None, DrawingMode::Position) => Arc::new(PositionIntegrator),
+            (None, DrawingMode::Uv) => Arc::new(UvIntegrator),
+            (None, DrawingMode::Barycentric) => Arc::new(BarycentricIntegrator),
+            (None, DrawingMode::FaceOrientation) => Arc::new(FaceOrientationIntegrator),
+            (None, DrawingMode::Ids(kind)) => Arc::new(IdIntegrator { kind }),
+        };
+
         RayTracer {
-            camera: Camera::with_aspect_ratio(aspect_ratio),
+            camera: Camera::new(config.camera, aspect_ratio),
+            integrator,
             config,
+            region,
             world: World::new(),
+            bounds: None,
         }
     }
 
-    pub fn add_mesh(&mut self, mesh: Mesh) {
-        self.world.add(mesh);
+    /// Width and height of the rendered image, smaller than the configured size when rendering a region
+    pub fn output_size(&self) -> (u32, u32) {
+        (self.region.width, self.region.height)
+    }
+
+    /// Replace the integrator which calculates the color of the samples
+    /// # Arguments
+    /// * 'integrator' - New integrator, e.g. to render an AOV like ambient occlusion of the same scene after the beauty image
+    /// # Notes
+    /// * The drawing mode still decides how many samples each pixel gets
+    pub fn set_integrator(&mut self, integrator: impl Integrator + 'static) {
+        self.integrator = Arc::new(integrator);
+    }
+
+    /// The drawing mode used to render
+    pub fn mode(&self) -> DrawingMode {
+        self.config.mode
+    }
+
+    /// Add a mesh to the world
+    /// # Returns
+    /// * The mesh's object ID, which render_ids and the ID modes show for it
+    pub fn add_mesh(&mut self, mesh: Mesh) -> u32 {
+        self.grow_bounds(Aabb::new(&mesh));
+        self.world.add(mesh)
+    }
+
+    /// Add an instance of a shared mesh to the world
+    /// # Returns
+    /// * The instance's object ID, which render_ids and the ID modes show for it
+    pub fn add_instance(&mut self, instance: Instance) -> u32 {
+        self.grow_bounds(instance.bounds());
+        self.world.add_instance(instance)
+    }
+
+    /// Add every mesh of a scene graph to the world
+    /// # Arguments
+    /// * 'root' - Root of the graph, its meshes are added as instances with their world transforms
+    /// # Notes
+    /// * The graph is walked in the same order each time, so a scene always gets the same object IDs
+    pub fn add_node(&mut self, root: &Node) {
+        for instance in root.instances() {
+            self.add_instance(instance);
+        }
+    }
+
+    /// Grow the box around the world to fit something added to it
+    fn grow_bounds(&mut self, bounds: Option<Aabb>) {
+        self.bounds = match (self.bounds, bounds) {
+            (Some(a), Some(b)) => Some(a.union(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    /// All the meshes in the world, e.g. to export them with write_obj
+    /// # Returns
+    /// * The meshes added with add_mesh, followed by a world space copy of each instance's mesh
+    /// # Notes
+    /// * Scenes are added as instances, so their meshes are copied out with the transforms of the scene graph
+    pub fn meshes(&self) -> Vec<Mesh> {
+        self.world
+            .meshes()
+            .iter()
+            .cloned()
+            .chain(self.world.instances().iter().map(Instance::world_mesh))
+            .collect()
     }
 
     pub fn run_sequential(&self, output: &mut dyn Write) -> Result<()> {
         self.write_header(output)?;
-
-        // Loop through our image
-        for y in (0..self.config.height).rev() {
-            for x in 0..self.config.width {
-                let pixel = self.generate_pixel(x, y);
-                self.write_color(output, pixel)?;
+        let progress = ProgressTracker::new(self.region.height, self.config.on_progress.as_ref());
+
+        // The whole image is one area, so samples are only traced once even with a wide filter
+        let area = Region {
+            x: 0,
+            y: 0,
+            ..self.region
+        };
+        let mut film = vec![FilmPixel::default(); (area.width * area.height) as usize];
+        let finished = self.splat_area(area, &mut film, &PixelSamples::Mode, &mut || {
+            if self.config.cancel.is_cancelled() {
+                return false;
             }
+            progress.done();
+            true
+        });
+        if !finished {
+            return Err(Error::Cancelled);
+        }
+
+        for pixel in &film {
+            self.write_color(output, pixel.resolve())?;
         }
 
         Ok(())
     }
 
     pub fn run_parallel(&self, output: &mut dyn Write) -> Result<()> {
-        self.write_header(output)?;
+        let mut writer = ImageWriter::new(
+            output,
+            self.region.width,
+            self.region.height,
+            ImageFormat::Ppm,
+        )?;
+        self.render_tiles(&mut writer)?;
+        writer.finish()
+    }
 
-        let this = Arc::new(self);
+    /// Render the image in parallel
+    /// # Returns
+    /// * The rendered image, which can be written as a PPM or PNG
+    /// * Error::Cancelled if the config's cancel token was cancelled
+    pub fn render(&self) -> Result<Image> {
+        let mut image = Image::new(self.region.width, self.region.height);
+        self.render_tiles(&mut image)?;
+        Ok(image)
+    }
 
-        // Loop through our image
-        let pixels: Vec<Vec3> = (0..this.config.height)
+    /// Find the object or material ID seen through each pixel, e.g. as a mask to composite the image with
+    /// # Arguments
+    /// * 'kind' - Which ID to find
+    /// # Returns
+    /// * The IDs of the rendered region, from one ray through the corner of each pixel like the colors mode
+    /// * Error::Cancelled if the config's cancel token was cancelled
+    /// # Notes
+    /// * IDs can't be averaged, so the edges of objects don't get the filtered samples of the sampled modes
+    pub fn render_ids(&self, kind: IdKind) -> Result<IdBuffer> {
+        let rows: Option<Vec<Vec<Option<u32>>>> = (0..self.region.height)
             .into_par_iter()
-            .rev()
-            .flat_map(|y| {
-                let this = this.clone();
-                (0..this.config.width)
-                    .into_par_iter()
-                    .map(move |x| this.generate_pixel(x, y))
+            .map(|row| {
+                if self.config.cancel.is_cancelled() {
+                    return None;
+                }
+
+                // The image's y values start at the bottom row
+                let y = self.config.height - 1 - self.region.y - row;
+                let columns = self.region.x..self.region.x + self.region.width;
+                Some(
+                    columns
+                        .map(|x| self.world.hit(self.corner_ray(x, y)).id(kind))
+                        .collect(),
+                )
             })
             .collect();
 
-        for pixel in pixels {
-            this.write_color(output, pixel)?;
-        }
-
-        Ok(())
+        Ok(IdBuffer {
+            width: self.region.width,
+            height: self.region.height,
+            ids: rows.ok_or(Error::Cancelled)?.concat(),
+        })
     }
 
-    fn generate_pixel(&self, x: u32, y: u32) -> Vec3 {
-        match self.config.mode {
-            DrawingMode::Colors | DrawingMode::Normals => {
-                let u = x as f64 / (self.config.width - 1) as f64;
-                let v = y as f64 / (self.config.height - 1) as f64;
-
-                // Calculate the ray based on the pixel we are on
-                let r = Ray::new(
-                    self.camera.origin,
-                    self.camera.lower_left_corner
-                        + (self.camera.horizontal * u)
-                        + (self.camera.vertical * v)
-                        - self.camera.origin,
-                );
+    /// Render the image in parallel tiles, handing each one over as soon as it's finished
+    /// # Arguments
+    /// * 'sink' - Receives the finished tiles on this thread, e.g. an Image or ImageWriter
+    /// # Returns
+    /// * Error::Cancelled if the config's cancel token was cancelled, or the sink's error
+    pub fn render_tiles(&self, sink: &mut dyn TileSink) -> Result<()> {
+        let areas = tile::tiles(
+            self.region.width,
+            self.region.height,
+            self.config.tile_size,
+            self.config.tile_order,
+        );
+        let total = areas.len();
+        let progress = ProgressTracker::new(total as u32, self.config.on_progress.as_ref());
+
+        // Set when the sink fails, so the remaining tiles are skipped
+        let failed = AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel::<Tile>();
+
+        thread::scope(|scope| {
+            // The rendering runs on another thread so this one can feed the sink
+            let (failed, progress) = (&failed, &progress);
+            scope.spawn(move || {
+                // Bridging keeps the tiles roughly in order, as each thread takes the next one
+                areas
+                    .into_iter()
+                    .par_bridge()
+                    .for_each_with(sender, |sender, area| {
+                        if failed.load(Ordering::Relaxed) || self.config.cancel.is_cancelled() {
+                            return;
+                        }
+                        let tile = self.render_tile(area);
+                        progress.done();
+
+                        // Sending only fails once the sink failed and stopped receiving
+                        let _ = sender.send(tile);
+                    });
+            });
+
+            let mut received = 0;
+            for tile in receiver {
+                if let Err(e) = sink.write_tile(&tile) {
+                    failed.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+                received += 1;
+            }
 
-                // Send over the ray and world and figure out the color we should draw for this pixel
-                self.ray_color(r, self.config.max_depth)
+            // Tiles are only missing if the render was cancelled
+            if received < total {
+                return Err(Error::Cancelled);
             }
-            DrawingMode::Samples(samples) => {
-                let mut color = Vec3::new(0.0, 0.0, 0.0);
+            Ok(())
+        })
+    }
 
-                // Loop for however many samples we want to take
-                for _ in 0..samples {
-                    // Need random number generator from 0-1
-                    let mut rng = rand::thread_rng();
+    /// Render the image in passes which each double the samples per pixel, 1, 2, 4, ...
+    /// # Arguments
+    /// * 'budget' - When to stop refining the image
+    /// * 'on_pass' - Called with the image after each pass, e.g. to show or write a preview, an error stops the render
+    /// # Returns
+    /// * The image of the last finished pass, also when the render is cancelled during a later pass
+    /// * Error::Cancelled if the render was cancelled before the first pass finished
+    /// # Notes
+    /// * Only DrawingMode::Samples and AmbientOcclusion are refined, adaptive sampling isn't used as the passes already spread the samples
+    /// * The other modes finish after one pass
+    /// * Progress is reported for the tiles of each pass
+    /// * Each pixel continues its sequence of samples, so with the default box filter a budget of n samples matches a Samples(n) render when n is a power of two
+    pub fn render_progressive(
+        &self,
+        budget: Budget,
+        on_pass: &mut dyn FnMut(&Image, Pass) -> Result<()>,
+    ) -> Result<Image> {
+        let start = Instant::now();
+        let max_samples = match self.config.mode.samples() {
+            Some(_) => budget.samples.unwrap_or(u32::MAX).max(1),
+            None => 1,
+        };
+
+        // Sums of the samples of each pixel, row by row from the top left
+        let mut film =
+            vec![FilmPixel::default(); (self.region.width * self.region.height) as usize];
+
+        let mut image = None;
+        let mut taken = 0;
+        let mut index = 0;
+        while taken < max_samples {
+            let mut samples = taken.max(1).min(max_samples - taken);
+
+            // Shorten the pass to the time left, assuming samples keep taking as long as they have so far
+            if let (Some(time), true) = (budget.time, taken > 0) {
+                let elapsed = start.elapsed();
+                let per_sample = elapsed.as_secs_f64() / taken as f64;
+                let fits = time.saturating_sub(elapsed).as_secs_f64() / per_sample;
+                samples = samples.min(fits as u32);
+                if samples == 0 {
+                    break;
+                }
+            }
 
-                    // Calculate u&v based on our random samples
-                    let u: f64 = ((x) as f64 + rng.gen::<f64>()) / (self.config.width - 1) as f64;
-                    let v: f64 = (y as f64 + rng.gen::<f64>()) / (self.config.height - 1) as f64;
+            if !self.render_pass(&mut film, taken..taken + samples, max_samples) {
+                break;
+            }
+            taken += samples;
+
+            let pass_image = Image {
+                width: self.region.width,
+                height: self.region.height,
+                pixels: film
+                    .iter()
+                    .map(|pixel| self.to_rgb(pixel.resolve()))
+                    .collect(),
+            };
+            let noise = film
+                .iter()
+                .map(|pixel| pixel.luminance.noise())
+                .sum::<f64>()
+                / film.len().max(1) as f64;
+            let elapsed = start.elapsed();
+            on_pass(
+                &pass_image,
+                Pass {
+                    index,
+                    samples: taken,
+                    elapsed,
+                    noise,
+                },
+            )?;
+            image = Some(pass_image);
+            index += 1;
+
+            if budget.noise.is_some_and(|target| noise <= target)
+                || budget.time.is_some_and(|time| elapsed >= time)
+            {
+                break;
+            }
+        }
 
-                    let r = Ray::new(
-                        self.camera.origin,
-                        self.camera.lower_left_corner
-                            + (self.camera.horizontal * u)
-                            + (self.camera.vertical * v)
-                            - self.camera.origin,
-                    );
+        image.ok_or(Error::Cancelled)
+    }
 
-                    // Add to the color for each sample, essentially creating an average color
-                    color = color + self.ray_color(r, self.config.max_depth);
+    /// Add samples to every pixel, in parallel tiles
+    /// # Arguments
+    /// * 'film' - Sums of the samples of each pixel, row by row from the top left
+    /// * 'samples' - Indices of the samples to take for each pixel
+    /// * 'max_samples' - Samples which will be taken at most, which the stratified sampler divides its cells by
+    /// # Returns
+    /// * false if the render was cancelled, leaving the film as it was
+    fn render_pass(&self, film: &mut [FilmPixel], samples: Range<u32>, max_samples: u32) -> bool {
+        let areas = tile::tiles(
+            self.region.width,
+            self.region.height,
+            self.config.tile_size,
+            self.config.tile_order,
+        );
+        let progress = ProgressTracker::new(areas.len() as u32, self.config.on_progress.as_ref());
+        let samples = PixelSamples::Range(
+            samples,
+            if max_samples == u32::MAX {
+                1
+            } else {
+                max_samples
+            },
+        );
+
+        // Each tile continues from the sums of its pixels, they're only written back once every tile is finished
+        let width = self.region.width as usize;
+        let source: &[FilmPixel] = film;
+        let tiles: Vec<Option<(Region, Vec<FilmPixel>)>> = areas
+            .into_par_iter()
+            .map(|area| {
+                if self.config.cancel.is_cancelled() {
+                    return None;
                 }
-                color
+                let mut tile_film: Vec<FilmPixel> = (0..area.height as usize)
+                    .flat_map(|row| {
+                        let start = (area.y as usize + row) * width + area.x as usize;
+                        source[start..start + area.width as usize].iter().copied()
+                    })
+                    .collect();
+                self.splat_area(area, &mut tile_film, &samples, &mut || true);
+                progress.done();
+                Some((area, tile_film))
+            })
+            .collect();
+        if self.config.cancel.is_cancelled() {
+            return false;
+        }
+
+        for (area, tile_film) in tiles.into_iter().flatten() {
+            for (row, pixels) in tile_film.chunks(area.width as usize).enumerate() {
+                let start = (area.y as usize + row) * width + area.x as usize;
+                film[start..start + pixels.len()].copy_from_slice(pixels);
             }
         }
+        true
     }
 
-    /// Calculate color based on the ray and whatever it hits
+    /// Render the pixels of a tile
     /// # Arguments
-    /// * 'r' - Ray to cast
-    /// * 'depth' - Number of bounces a ray can have
+    /// * 'area' - Part of the rendered image, in pixels from its top left corner
+    fn render_tile(&self, area: Region) -> Tile {
+        let mut film = vec![FilmPixel::default(); (area.width * area.height) as usize];
+        self.splat_area(area, &mut film, &PixelSamples::Mode, &mut || true);
+        let pixels = film
+            .iter()
+            .map(|pixel| self.to_rgb(pixel.resolve()))
+            .collect();
+        Tile { area, pixels }
+    }
+
+    /// Trace the samples which fall on an area and add them up, weighted by the reconstruction filter
+    /// # Arguments
+    /// * 'area' - Part of the rendered image, in pixels from its top left corner
+    /// * 'film' - Sums of the area's pixels, row by row from the top left, which the samples are added to
+    /// * 'samples' - Which samples to take of each pixel
+    /// * 'on_row' - Called after each row of the area, returning false stops the render
     /// # Returns
-    /// * Vec3 which contains r,g,b values in the x,y,z position of the vector
-    fn ray_color(&self, r: Ray, depth: u32) -> Vec3 {
-        // Check if our ray hits any object
-        // Hit will contain details about the object the ray hit
-        let hit = self.world.hit(r);
-
-        // Match the drawing mode
-        match self.config.mode {
-            DrawingMode::Colors => {
-                // Hit.t will be > 0 if the ray actually hit something
-                if hit.t > 0.0 {
-                    // Simply return the color of what the ray hit
-                    return hit.material.get_albedo();
+    /// * false if on_row stopped the render
+    /// # Notes
+    /// * Pixels around the area are traced as well when their samples reach into it, so areas can be rendered independently
+    /// * Pixels are traced from the top row down and left to right, the same order for any area, so the sums don't depend on the tiles
+    fn splat_area(
+        &self,
+        area: Region,
+        film: &mut [FilmPixel],
+        samples: &PixelSamples,
+        on_row: &mut dyn FnMut() -> bool,
+    ) -> bool {
+        // A region clipped off the image has nothing to trace, and no rows to find the edges of
+        if area.width == 0 || area.height == 0 {
+            return true;
+        }
+
+        // The image's y values start at the bottom row
+        let top = self.config.height - 1 - self.region.y - area.y;
+        let bottom = top + 1 - area.height;
+        let left = self.region.x + area.x;
+        let right = left + area.width - 1;
+        let index = |x: u32, y: u32| ((top - y) * area.width + x - left) as usize;
+
+        if self.config.mode.samples().is_none() {
+            for y in (bottom..=top).rev() {
+                for x in left..=right {
+                    let pixel = &mut film[index(x, y)];
+                    pixel.color = self.generate_pixel(x, y);
+                    pixel.weight = 1.0;
                 }
-            }
-            DrawingMode::Normals => {
-                if hit.t > 0.0 {
-                    // If the mesh is smooth shaded, we need to calculate the interpolated normal
-                    let n = if hit.triangle.smooth {
-                        // Calculate the barycentric coordinates
-                        let bary = barycentric(hit.clone());
-
-                        // Calculate the interpolated normal
-                        unit_vector(
-                            hit.triangle.normals[0] * bary.x
-                                + hit.triangle.normals[1] * bary.y
-                                + hit.triangle.normals[2] * bary.z,
-                        )
-                    } else {
-                        // Mesh isn't smooth shaded, simply return its single normal
-                        hit.triangle.normal
-                    };
-
-                    // Calculate color based on the normal
-                    return Vec3::new(n.x + 1.0, n.y + 1.0, n.z + 1.0) * 0.5;
+                if !on_row() {
+                    return false;
                 }
             }
-            DrawingMode::Samples(_) => {
-                // Samples mode recursively calls ray_color
-                // Quit recursively calling if we've bounced our last bounce
-                if depth == 0 {
-                    return Vec3::new(0.0, 0.0, 0.0);
+            return true;
+        }
+
+        let filter = self.config.filter;
+        let margin = filter.margin();
+        let sources_x = left.saturating_sub(margin)..=(right + margin).min(self.config.width - 1);
+        let sources_y = bottom.saturating_sub(margin)..=(top + margin).min(self.config.height - 1);
+        for y in sources_y.rev() {
+            for x in sources_x.clone() {
+                // Only the area's own pixels keep track of their noise
+                let own = (left..=right).contains(&x) && (bottom..=top).contains(&y);
+                let mut luminance = if own {
+                    film[index(x, y)].luminance
+                } else {
+                    Luminance::default()
+                };
+
+                let targets_x = x.saturating_sub(margin).max(left)..=(x + margin).min(right);
+                let targets_y = y.saturating_sub(margin).max(bottom)..=(y + margin).min(top);
+                self.trace_pixel(
+                    x,
+                    y,
+                    samples,
+                    &mut luminance,
+                    &mut |color, [jitter_x, jitter_y]| {
+                        for target_y in targets_y.clone() {
+                            for target_x in targets_x.clone() {
+                                let weight = filter.weight(
+                                    x as f64 + jitter_x - (target_x as f64 + 0.5),
+                                    y as f64 + jitter_y - (target_y as f64 + 0.5),
+                                );
+                                if weight != 0.0 {
+                                    let pixel = &mut film[index(target_x, target_y)];
+                                    pixel.color = pixel.color + color * weight;
+                                    pixel.weight += weight;
+                                }
+                            }
+                        }
+                    },
+                );
+
+                if own {
+                    film[index(x, y)].luminance = luminance;
                 }
-                if hit.t > 0.0 {
-                    // Will store the new ray, i.e. we bounce off the object and have a new ray based on the bounce
-                    let mut scattered =
-                        Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
-
-                    // Store the current color of whatever the ray bounces off
-                    let mut attenuation = Vec3::new(0.0, 0.0, 0.0);
-
-                    // Make sure we correctly scatter based on the objects material
-                    if hit
-                        .material
-                        .scatter(r, hit.clone(), &mut attenuation, &mut scattered)
-                    {
-                        // Recursively call, multiplying the current color
-                        return attenuation * self.ray_color(scattered, depth - 1);
+            }
+            if (bottom..=top).contains(&y) && !on_row() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Calculate the color of a pixel in the modes with one sample in its corner, like colors and normals
+    /// # Arguments
+    /// * 'x, y' - Pixel, y starts at the bottom row
+    fn generate_pixel(&self, x: u32, y: u32) -> Vec3 {
+        let r = self.corner_ray(x, y);
+
+        // Send over the ray and world and figure out the color we should draw for this pixel
+        let mut sampler = self.config.sampler.create(self.config.seed, 1);
+        sampler.start_sample(x, y, 0);
+        self.ray_color(r, sampler.as_mut())
+    }
+
+    /// Calculate the ray through the corner of a pixel
+    /// # Arguments
+    /// * 'x, y' - Pixel, y starts at the bottom row
+    fn corner_ray(&self, x: u32, y: u32) -> Ray {
+        let u = x as f64 / (self.config.width - 1) as f64;
+        let v = y as f64 / (self.config.height - 1) as f64;
+
+        // Calculate the ray based on the pixel we are on
+        Ray::new(
+            self.camera.origin,
+            self.camera.lower_left_corner
+                + (self.camera.horizontal * u)
+                + (self.camera.vertical * v)
+                - self.camera.origin,
+        )
+    }
+
+    /// Trace the samples of a pixel
+    /// # Arguments
+    /// * 'x, y' - Pixel, y starts at the bottom row
+    /// * 'samples' - Which samples to take
+    /// * 'luminance' - Sums of the pixel's samples so far, adaptive sampling stops once they're converged
+    /// * 'on_sample' - Called with the color of each sample and its position within the pixel
+    fn trace_pixel(
+        &self,
+        x: u32,
+        y: u32,
+        samples: &PixelSamples,
+        luminance: &mut Luminance,
+        on_sample: &mut dyn FnMut(Vec3, [f64; 2]),
+    ) {
+        // The samples are spread over strata, e.g. the cells of the stratified sampler
+        let (range, batch, strata, adaptive) = match samples {
+            PixelSamples::Mode => {
+                let Some(samples) = self.config.mode.samples() else {
+                    return;
+                };
+
+                // Adaptive sampling takes batches of the mode's samples until the pixel converges,
+                // each batch is stratified on its own so a pixel which stops early is still evenly spread
+                match self.config.adaptive {
+                    Some(adaptive) => {
+                        let max_samples = adaptive.max_samples.max(samples);
+                        let batch = samples.max(1);
+                        (0..max_samples, batch, batch, Some(adaptive))
                     }
+                    None => (0..samples, samples, samples, None),
                 }
             }
+            PixelSamples::Range(range, max_samples) => {
+                (range.clone(), range.len() as u32, *max_samples, None)
+            }
+        };
+
+        // Random values from 0-1, which only depend on the seed, pixel and sample so the order pixels are rendered in doesn't matter
+        let mut sampler = self.config.sampler.create(self.config.seed, strata);
+
+        let mut taken = range.start;
+        while taken < range.end {
+            // Loop for however many samples we want to take
+            let end = (taken + batch).min(range.end);
+            for sample in taken..end {
+                let (color, jitter) = self.sample_pixel(x, y, sample, sampler.as_mut());
+                luminance.add(color);
+                on_sample(color, jitter);
+            }
+            taken = end;
+
+            match adaptive {
+                Some(adaptive) if luminance.noise() > adaptive.noise_threshold => {}
+                _ => break,
+            }
         }
+    }
 
-        // This code generates the blueish gradient background
-        let n = r.direction;
-        let t = (n.y + 1.0) * 0.5;
+    /// Trace one sample of a pixel
+    /// # Arguments
+    /// * 'x, y' - Pixel, y starts at the bottom row
+    /// * 'sample' - Index of the sample within the pixel
+    /// * 'sampler' - Sampler of the pixel, which is started at the sample
+    /// # Returns
+    /// * The color of the sample and its position within the pixel, from 0-1
+    fn sample_pixel(
+        &self,
+        x: u32,
+        y: u32,
+        sample: u32,
+        sampler: &mut dyn Sampler,
+    ) -> (Vec3, [f64; 2]) {
+        sampler.start_sample(x, y, sample);
+
+        // Calculate u&v based on our random samples
+        let [jitter_x, jitter_y] = sampler.pixel();
+        let u: f64 = (x as f64 + jitter_x) / (self.config.width - 1) as f64;
+        let v: f64 = (y as f64 + jitter_y) / (self.config.height - 1) as f64;
+
+        // The camera has no lens or shutter, their dimensions are still drawn so the bounces keep theirs if it gets them
+        sampler.lens();
+        sampler.time();
+
+        let r = Ray::new(
+            self.camera.origin,
+            self.camera.lower_left_corner
+                + (self.camera.horizontal * u)
+                + (self.camera.vertical * v)
+                - self.camera.origin,
+        );
+        (self.ray_color(r, sampler), [jitter_x, jitter_y])
+    }
 
-        // Typical interpolation
-        (Vec3::new(1.0, 1.0, 1.0) * (1.0 - t)) + Vec3::new(0.5, 0.7, 1.0) * t
+    /// Calculate the color of a sample with the integrator
+    /// # Arguments
+    /// * 'r' - Ray leaving the camera
+    /// * 'sampler' - Random values of the sample
+    fn ray_color(&self, r: Ray, sampler: &mut dyn Sampler) -> Vec3 {
+        let scene = SceneView::new(&self.world, self.config.background, self.bounds);
+        self.integrator.radiance(r, &scene, sampler)
     }
 
     fn write_header(&self, output: &mut dyn Write) -> Result<()> {
         output.write_all(
-            format!("P3\n{} {}\n255\n", self.config.width, self.config.height).as_bytes(),
+            format!("P3\n{} {}\n255\n", self.region.width, self.region.height).as_bytes(),
         )?;
 
         Ok(())
@@ -206,30 +698,212 @@ impl RayTracer {
     /// * 'output' - PPM file we write to
     /// * 'color' - Color which we wish to write
     fn write_color(&self, output: &mut dyn Write, color: Vec3) -> Result<()> {
-        let r: u32;
-        let g: u32;
-        let b: u32;
-        match self.config.mode {
-            DrawingMode::Colors | DrawingMode::Normals => {
-                // If we're drawing colors/normals, simply multiply by 255
-                // Input color is 0-1, so multiply by 255 to make it in a range of 0-255
-                r = (color.x * 255.0) as u32;
-                g = (color.y * 255.0) as u32;
-                b = (color.z * 255.0) as u32;
-            }
-            DrawingMode::Samples(samples) => {
-                // Perform gamma correction
-                r = ((color.x * (1.0 / samples as f64)).sqrt().clamp(0.0, 0.999) * 255.0) as u32;
-                g = ((color.y * (1.0 / samples as f64)).sqrt().clamp(0.0, 0.999) * 255.0) as u32;
-                b = ((color.z * (1.0 / samples as f64)).sqrt().clamp(0.0, 0.999) * 255.0) as u32;
+        let [r, g, b] = self.to_rgb(color);
+        output.write_all(format!("{} {} {}\n", r, g, b).as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Convert a color to 8 bit r,g,b values
+    /// # Arguments
+    /// * 'color' - Color returned by generate_pixel
+    fn to_rgb(&self, color: Vec3) -> [u8; 3] {
+        let [r, g, b] = [color.x, color.y, color.z].map(|c| {
+            if self.integrator.gamma_correct() {
+                // Perform gamma correction, the color is already averaged over the samples
+                // Filters with negative lobes can make colors negative, which are black
+                (c.max(0.0).sqrt().clamp(0.0, 0.999) * 255.0) as u8
+            } else {
+                // Debug views are 0-1, so multiply by 255 to make it in a range of 0-255
+                (c.clamp(0.0, 1.0) * 255.0) as u8
             }
+        });
+        [r, g, b]
+    }
+}
+
+/// Which samples of each pixel to take
+/// * 'Mode' - The samples of the drawing mode, with adaptive sampling if it's enabled
+/// * 'Range' - The samples with these indices, out of the most samples the render will take
+enum PixelSamples {
+    Mode,
+    Range(Range<u32>, u32),
+}
+
+/// Samples added up for a pixel, weighted by the reconstruction filter
+#[derive(Copy, Clone)]
+struct FilmPixel {
+    color: Vec3,
+    weight: f64,
+    /// Only of the pixel's own samples
+    luminance: Luminance,
+}
+
+impl Default for FilmPixel {
+    fn default() -> Self {
+        Self {
+            color: Vec3::new(0.0, 0.0, 0.0),
+            weight: 0.0,
+            luminance: Luminance::default(),
         }
-        if r > 255 || g > 255 || b > 255 {
-            panic!("Color value out of range");
+    }
+}
+
+impl FilmPixel {
+    /// The weighted average of the samples, black without any
+    fn resolve(&self) -> Vec3 {
+        if self.weight > 0.0 {
+            self.color * (1.0 / self.weight)
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
         }
+    }
+}
 
-        output.write_all(format!("{} {} {}\n", r, g, b).as_bytes())?;
+/// Running sums of a pixel's sample luminances, to estimate how noisy the pixel still is
+#[derive(Copy, Clone, Default)]
+struct Luminance {
+    count: u32,
+    sum: f64,
+    sum_squared: f64,
+}
 
-        Ok(())
+impl Luminance {
+    /// Add the color of a sample
+    fn add(&mut self, color: Vec3) {
+        let luminance = 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+        self.count += 1;
+        self.sum += luminance;
+        self.sum_squared += luminance * luminance;
+    }
+
+    /// Standard error of the gamma corrected luminance
+    /// # Notes
+    /// * The square root of the gamma correction changes by about d / (2 * sqrt(mean)) when the mean changes by d
+    fn noise(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.count as f64;
+        let mean = self.sum / n;
+        let variance = ((self.sum_squared - self.sum * mean) / (n - 1.0)).max(0.0);
+        if variance == 0.0 {
+            return 0.0;
+        }
+        (variance / n).sqrt() / (2.0 * mean.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{Filter, FilterKind, TileOrder},
+        quad, uv_sphere, AdaptiveSampling, Background, CameraSettings, RayTracerConfig,
+        SamplerKind, Transform, Vec3,
+    };
+
+    /// A small scene with a shared mesh, rendered with samples crossing tile edges
+    fn scene(filter: Filter) -> RayTracer {
+        let mut tracer = RayTracerConfig::default()
+            .width(23)
+            .height(17)
+            .mode(DrawingMode::Samples(3))
+            .max_depth(4)
+            .seed(7)
+            .filter(filter)
+            .tile_size(8)
+            .tile_order(TileOrder::Hilbert)
+            .camera(CameraSettings {
+                position: Vec3::new(0.0, 1.0, 4.0),
+                look_at: Vec3::new(0.0, 0.0, 0.0),
+                ..CameraSettings::default()
+            })
+            .build();
+        tracer.add_mesh(quad(6.0, 6.0));
+        let sphere = Arc::new(uv_sphere(0.8, 12, 8));
+        for x in [-1.0, 1.0] {
+            tracer.add_instance(Instance::new(
+                sphere.clone(),
+                Transform::translate(Vec3::new(x, 0.8, 0.0)),
+            ));
+        }
+        tracer
+    }
+
+    #[test]
+    fn sequential_and_parallel_render_the_same_bytes() {
+        for filter in [Filter::default(), Filter::new(FilterKind::Gaussian)] {
+            let tracer = scene(filter);
+            let mut sequential = Vec::new();
+            tracer.run_sequential(&mut sequential).unwrap();
+            let mut parallel = Vec::new();
+            tracer.run_parallel(&mut parallel).unwrap();
+
+            assert!(!sequential.is_empty());
+            assert!(
+                sequential == parallel,
+                "{:?} filter renders differ",
+                filter.kind
+            );
+        }
+    }
+
+    #[test]
+    fn region_clipped_off_the_image_renders_nothing() {
+        let tracer = RayTracerConfig::default()
+            .width(8)
+            .height(8)
+            .region(Region {
+                x: 0,
+                y: 20,
+                width: 4,
+                height: 4,
+            })
+            .build();
+
+        let mut sequential = Vec::new();
+        tracer.run_sequential(&mut sequential).unwrap();
+        let mut parallel = Vec::new();
+        tracer.run_parallel(&mut parallel).unwrap();
+        assert!(sequential == parallel);
+        assert!(tracer.render().unwrap().pixels.is_empty());
+    }
+
+    #[test]
+    fn converged_pixels_stop_after_their_first_batch() {
+        // A plain background is converged right away, the floor next to the sphere isn't
+        let mut tracer = RayTracerConfig::default()
+            .width(8)
+            .height(8)
+            .mode(DrawingMode::Samples(4))
+            .adaptive(AdaptiveSampling {
+                max_samples: 64,
+                noise_threshold: 0.001,
+            })
+            .sampler(SamplerKind::Stratified)
+            .background(Background::Color(Vec3::new(0.5, 0.5, 0.5)))
+            .camera(CameraSettings {
+                position: Vec3::new(0.0, 1.0, 4.0),
+                look_at: Vec3::new(0.0, 0.0, 0.0),
+                ..CameraSettings::default()
+            })
+            .build();
+        tracer.add_mesh(quad(6.0, 6.0));
+        tracer.add_mesh(uv_sphere(0.8, 12, 8));
+
+        let samples_taken = |x, y| {
+            let mut taken = 0;
+            tracer.trace_pixel(
+                x,
+                y,
+                &PixelSamples::Mode,
+                &mut Luminance::default(),
+                &mut |_, _| taken += 1,
+            );
+            taken
+        };
+        assert_eq!(samples_taken(0, 7), 4);
+        assert!(samples_taken(4, 2) > 4);
     }
 }
@@ -0,0 +1,456 @@
+use std::ops::Mul;
+
+use crate::{
+    vec3::{cross, dot, unit_vector},
+    Vec3,
+};
+
+/// Row-major 4x4 matrix for affine transforms of column vectors
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    /// Values indexed by [row][column]
+    pub m: [[f64; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mat4 {
+    /// Create a new matrix
+    /// # Arguments
+    /// * 'm' - Values indexed by [row][column]
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    /// Create the identity matrix
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Self { m }
+    }
+
+    /// Create a matrix from column-major values, as stored by glTF
+    /// # Arguments
+    /// * 'columns' - Values indexed by [column][row]
+    pub fn from_columns(columns: [[f64; 4]; 4]) -> Self {
+        Self { m: columns }.transpose()
+    }
+
+    /// Swap the rows and columns
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (row, values) in m.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = self.m[column][row];
+            }
+        }
+        Mat4 { m }
+    }
+
+    /// Calculate the inverse with Gauss-Jordan elimination
+    /// # Returns
+    /// * The inverse, None if the matrix is singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inverse = Mat4::identity().m;
+
+        for column in 0..4 {
+            // Use the largest remaining value as the pivot to keep rounding errors small
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap_or(column);
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for k in 0..4 {
+                a[column][k] *= scale;
+                inverse[column][k] *= scale;
+            }
+
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for k in 0..4 {
+                        a[row][k] -= factor * a[column][k];
+                        inverse[row][k] -= factor * inverse[column][k];
+                    }
+                }
+            }
+        }
+
+        Some(Mat4 { m: inverse })
+    }
+
+    /// Transform a position, including the translation
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.transform_vector(p) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    /// Transform a direction, ignoring the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /// Transform a normal by the inverse transpose of the upper 3x3, so it stays perpendicular to the surface
+    /// # Returns
+    /// * Unit normal vector, or the zero vector if the normal is collapsed
+    /// # Notes
+    /// * Uses the cofactor matrix, so this also works for singular matrices like a scale of 0
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        // The cofactor matrix is the inverse transpose scaled by the determinant
+        let [c0, c1, c2] = self.columns();
+        let cofactor = cross(c1, c2) * n.x + cross(c2, c0) * n.y + cross(c0, c1) * n.z;
+        let n = cofactor * self.determinant().signum();
+        if n.near_zero() {
+            n
+        } else {
+            unit_vector(n)
+        }
+    }
+
+    /// Determinant of the upper 3x3, negative if the matrix mirrors
+    pub fn determinant(&self) -> f64 {
+        let [c0, c1, c2] = self.columns();
+        dot(c0, cross(c1, c2))
+    }
+
+    /// The upper 3x3 columns
+    fn columns(&self) -> [Vec3; 3] {
+        [0, 1, 2].map(|c| Vec3::new(self.m[0][c], self.m[1][c], self.m[2][c]))
+    }
+}
+
+/// Multiply two matrices, the right one is applied first
+impl Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (row, values) in m.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[row][k] * rhs.m[k][column]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
+
+/// An invertible affine transform, keeping its inverse around for normals and rays
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    /// Create a transform which doesn't change anything
+    pub fn identity() -> Self {
+        Self {
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+
+    /// Create a transform from a matrix
+    /// # Arguments
+    /// * 'matrix' - Affine matrix
+    /// # Returns
+    /// * The transform, None if the matrix can't be inverted
+    pub fn from_matrix(matrix: Mat4) -> Option<Self> {
+        Some(Self {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    /// Create a translation
+    /// # Arguments
+    /// * 'd' - Vec3 which contains the x,y,z directions to translate
+    pub fn translate(d: Vec3) -> Self {
+        let translation = |d: Vec3| {
+            let mut m = Mat4::identity();
+            m.m[0][3] = d.x;
+            m.m[1][3] = d.y;
+            m.m[2][3] = d.z;
+            m
+        };
+        Self {
+            matrix: translation(d),
+            inverse: translation(d * -1.0),
+        }
+    }
+
+    /// Create a scale along the x,y,z axes
+    /// # Arguments
+    /// * 's' - Vec3 which contains the x,y,z factors
+    /// # Notes
+    /// * A factor of 0 collapses the axis, the inverse is then infinite so the transform can't be used for an instance
+    pub fn scale(s: Vec3) -> Self {
+        let scaling = |s: Vec3| {
+            let mut m = Mat4::identity();
+            m.m[0][0] = s.x;
+            m.m[1][1] = s.y;
+            m.m[2][2] = s.z;
+            m
+        };
+        Self {
+            matrix: scaling(s),
+            inverse: scaling(Vec3::new(1.0 / s.x, 1.0 / s.y, 1.0 / s.z)),
+        }
+    }
+
+    /// Create a rotation about an arbitrary axis through the origin
+    /// # Arguments
+    /// * 'axis' - Direction of the axis, doesn't need to be unit length
+    /// * 'degrees' - Counter-clockwise angle with the axis pointing at the viewer, in degrees NOT radians
+    pub fn rotate(axis: Vec3, degrees: f64) -> Self {
+        let a = unit_vector(axis);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+
+        // Rodrigues' rotation formula
+        let matrix = Mat4::new([
+            [
+                t * a.x * a.x + cos,
+                t * a.x * a.y - sin * a.z,
+                t * a.x * a.z + sin * a.y,
+                0.0,
+            ],
+            [
+                t * a.x * a.y + sin * a.z,
+                t * a.y * a.y + cos,
+                t * a.y * a.z - sin * a.x,
+                0.0,
+            ],
+            [
+                t * a.x * a.z - sin * a.y,
+                t * a.y * a.z + sin * a.x,
+                t * a.z * a.z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+
+        // Rotations are orthogonal, the inverse is the transpose
+        Self {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    /// Create a rotation about the x axis, then the y axis, then the z axis
+    /// # Arguments
+    /// * 'r' - Vec3 in degrees NOT radians
+    pub fn rotate_euler(r: Vec3) -> Self {
+        Self::rotate(Vec3::new(1.0, 0.0, 0.0), r.x)
+            .then(&Self::rotate(Vec3::new(0.0, 1.0, 0.0), r.y))
+            .then(&Self::rotate(Vec3::new(0.0, 0.0, 1.0), r.z))
+    }
+
+    /// Create a rotation from a quaternion
+    /// # Arguments
+    /// * 'q' - The quaternion as [x, y, z, w], as stored by glTF, it's normalized first
+    pub fn rotate_quaternion(q: [f64; 4]) -> Self {
+        let length = q.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if length == 0.0 {
+            return Self::identity();
+        }
+        let [x, y, z, w] = q.map(|v| v / length);
+
+        let matrix = Mat4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Self {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    /// Combine two transforms
+    /// # Arguments
+    /// * 'next' - Transform applied after this one
+    /// # Returns
+    /// * A transform which applies this one and then 'next'
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    /// The transform which undoes this one
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    /// The transform's matrix
+    pub fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    /// The matrix of the inverse transform
+    pub fn inverse_matrix(&self) -> &Mat4 {
+        &self.inverse
+    }
+
+    /// Transform a position, including the translation
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+
+    /// Transform a direction, ignoring the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    /// Transform a normal by the inverse transpose, so it stays perpendicular to the surface
+    /// # Returns
+    /// * Unit normal vector, or the zero vector for a zero or collapsed normal
+    /// # Notes
+    /// * Uses the matrix's cofactors rather than the stored inverse, which is infinite for a scale of 0
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        self.matrix.transform_normal(n)
+    }
+
+    /// Whether the transform mirrors, flipping the winding of triangles
+    pub fn is_mirroring(&self) -> bool {
+        self.matrix.determinant() < 0.0
+    }
+
+    /// Whether the transform collapses space onto a plane, line or point, e.g. a scale of 0
+    /// # Notes
+    /// * A singular transform has no usable inverse, so rays can't be moved into its object space
+    pub fn is_singular(&self) -> bool {
+        self.matrix.determinant().abs() < 1e-12
+            || self.inverse.m.iter().flatten().any(|v| !v.is_finite())
+    }
+}
+
+/// Combine two transforms like matrices, the right one is applied first
+impl Mul<Transform> for Transform {
+    type Output = Transform;
+    fn mul(self, rhs: Transform) -> Transform {
+        rhs.then(&self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Mesh, Triangle};
+
+    fn assert_identity(m: Mat4) {
+        let identity = Mat4::identity();
+        for (row, expected) in m.m.iter().zip(identity.m.iter()) {
+            for (value, expected) in row.iter().zip(expected) {
+                assert!(
+                    (value - expected).abs() < 1e-9,
+                    "{:?} isn't the identity",
+                    m
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let transform = Transform::scale(Vec3::new(2.0, -3.0, 0.5))
+            .then(&Transform::rotate(Vec3::new(1.0, 2.0, 3.0), 40.0))
+            .then(&Transform::translate(Vec3::new(4.0, -5.0, 6.0)));
+        let matrix = *transform.matrix();
+        let inverse = matrix.inverse().expect("the matrix isn't singular");
+
+        assert_identity(matrix * inverse);
+        assert_identity(inverse * matrix);
+        assert_identity(*transform.inverse_matrix() * matrix);
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        assert!(Transform::scale(Vec3::new(1.0, 0.0, 1.0))
+            .matrix()
+            .inverse()
+            .is_none());
+        assert!(Mat4::new([[0.0; 4]; 4]).inverse().is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Transform::scale(Vec3::new(4.0, 1.0, 1.0));
+        let normal = transform.transform_normal(unit_vector(Vec3::new(1.0, 1.0, 0.0)));
+        let tangent = transform.transform_vector(Vec3::new(1.0, -1.0, 0.0));
+        assert!(dot(normal, tangent).abs() < 1e-9);
+        assert!((normal.length() - 1.0).abs() < 1e-9);
+    }
+
+    /// A smooth shaded triangle with normals pointing along every axis
+    fn smooth_mesh() -> Mesh {
+        let mut trig = Triangle::new(
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            unit_vector(Vec3::new(1.0, 1.0, 1.0)),
+        );
+        trig.smooth = true;
+        trig.normals = trig.points;
+        Mesh::new_mesh(vec![trig])
+    }
+
+    #[test]
+    fn zero_scale_doesnt_make_nan_normals() {
+        let mut mesh = smooth_mesh();
+        mesh.scale(0.0);
+        assert!(mesh
+            .normals
+            .iter()
+            .all(|n| n.x.is_finite() && n.y.is_finite() && n.z.is_finite()));
+
+        let mut flattened = smooth_mesh();
+        flattened.transform(&Transform::scale(Vec3::new(1.0, 0.0, 1.0)));
+        assert!(flattened
+            .normals
+            .iter()
+            .all(|n| !n.x.is_nan() && !n.y.is_nan() && !n.z.is_nan()));
+    }
+}
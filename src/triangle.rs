@@ -1,5 +1,5 @@
 use crate::{
-    vec3::{cross, dot},
+    vec3::{cross, dot, unit_vector},
     Hit, Ray, Vec3,
 };
 
@@ -14,6 +14,12 @@ pub struct Triangle {
     pub smooth: bool,
     /// If smooth, contains 3 normals for the 3 points
     pub normals: [Vec3; 3],
+    /// Texture coordinates for the 3 points, if the mesh has any
+    pub uvs: Option<[[f64; 2]; 3]>,
+    /// Colors for the 3 points multiplied with the material's color, if the mesh has any
+    pub colors: Option<[Vec3; 3]>,
+    /// Index into the owning mesh's material table, None uses the mesh's default material
+    pub material: Option<usize>,
 }
 
 impl Triangle {
@@ -22,13 +28,16 @@ impl Triangle {
     /// * 'p1, p2, p3' - The three points of the triangle
     /// * 'n' - Triangle normal vector
     /// # Returns
-    /// * Triangle with given points and normal, smooth is default off and empty normals per vertex
+    /// * Triangle with given points and normal, smooth is default off, empty normals per vertex, no uvs or colors and no material index
     pub fn new(p1: Vec3, p2: Vec3, p3: Vec3, n: Vec3) -> Self {
         Self {
             points: [p1, p2, p3],
             normal: n,
             smooth: false,
             normals: [Vec3::new(0.0, 0.0, 0.0); 3],
+            uvs: None,
+            colors: None,
+            material: None,
         }
     }
 
@@ -46,47 +55,30 @@ impl Triangle {
 }
 
 impl Triangle {
+    /// Calculate the geometric normal from the counter-clockwise winding of the points
+    /// # Returns
+    /// * Unit normal vector, or the zero vector for a degenerate triangle
+    pub fn geometric_normal(&self) -> Vec3 {
+        geometric_normal(self.points)
+    }
+
     /// Check if the triangle has been hit by the ray
     /// # Arguments
     /// * 'r' - The incoming ray
     /// # Returns
     /// * Hit struct containing all the information of the triangle
-    /// # Credit
-    /// * Using Möller–Trumbore intersection algorithm
-    /// * The code was provided by Wikipedia in C++, translated by me
-    /// * <https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm>
     pub fn hit(&self, r: Ray) -> Hit {
         // Create an empty hit object, this will get populated the ray hits the triangle
         let mut hit = Hit::new();
-        let edge1 = self.points[1] - self.points[0];
-        let edge2 = self.points[2] - self.points[0];
-        let h = cross(r.direction, edge2);
-        let a = dot(edge1, h);
-        const EPSILON: f64 = 0.0000001;
-        if a > -EPSILON && a < EPSILON {
-            return hit;
-        }
-
-        let f = 1.0 / a;
-        let s = r.origin - self.points[0];
-        let u = f * dot(s, h);
-        if !(0.0..=1.0).contains(&u) {
-            return hit;
-        }
-
-        let q = cross(s, edge1);
-        let v = f * dot(r.direction, q);
-        if v < 0.0 || u + v > 1.0 {
-            return hit;
-        }
-
-        let t = f * dot(edge2, q);
 
         // If this is true, this means the ray hit the triangle
-        if t > EPSILON {
+        if let Some(t) = intersect(self.points, r) {
             let mut trig = Triangle::new_empty();
             trig.points = self.points;
             trig.normal = self.normal;
+            trig.uvs = self.uvs;
+            trig.colors = self.colors;
+            trig.material = self.material;
 
             if self.smooth {
                 trig.smooth = true;
@@ -96,9 +88,62 @@ impl Triangle {
             hit.triangle = trig;
             hit.t = t;
             hit.at = r.at(t);
-            hit
-        } else {
-            hit
         }
+        hit
+    }
+}
+
+/// Calculate the geometric normal of three counter-clockwise points
+/// # Returns
+/// * Unit normal vector, or the zero vector for a degenerate triangle
+pub(crate) fn geometric_normal(points: [Vec3; 3]) -> Vec3 {
+    let n = cross(points[1] - points[0], points[2] - points[0]);
+
+    // Degenerate triangles can't be hit anyway, just avoid dividing by zero
+    if n.near_zero() {
+        n
+    } else {
+        unit_vector(n)
+    }
+}
+
+/// Check if the triangle made by three points is hit by the ray
+/// # Arguments
+/// * 'points' - The points of the triangle
+/// * 'r' - The incoming ray
+/// # Returns
+/// * The t of the hit position along the ray, None if the ray misses
+/// # Credit
+/// * Using Möller–Trumbore intersection algorithm
+/// * The code was provided by Wikipedia in C++, translated by me
+/// * <https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm>
+pub(crate) fn intersect(points: [Vec3; 3], r: Ray) -> Option<f64> {
+    let edge1 = points[1] - points[0];
+    let edge2 = points[2] - points[0];
+    let h = cross(r.direction, edge2);
+    let a = dot(edge1, h);
+    const EPSILON: f64 = 0.0000001;
+    if a > -EPSILON && a < EPSILON {
+        return None;
+    }
+
+    let f = 1.0 / a;
+    let s = r.origin - points[0];
+    let u = f * dot(s, h);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = cross(s, edge1);
+    let v = f * dot(r.direction, q);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = f * dot(edge2, q);
+    if t > EPSILON {
+        Some(t)
+    } else {
+        None
     }
 }
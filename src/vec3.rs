@@ -1,7 +1,6 @@
+use std::f64::consts::PI;
 use std::ops::{Add, Div, Mul, Sub};
 
-use rand::Rng;
-
 use crate::Hit;
 
 /// Vec3 struct.
@@ -151,34 +150,49 @@ impl Mul<Vec3> for Vec3 {
     }
 }
 
-/// Calculate a random Vec3 given a range
+/// Map two uniform random values to a point on the unit sphere, spread evenly
 /// # Arguments
-/// * 'min, max' - Minimum and maximum values for the Vec3
+/// * 'u' - Values from 0-1, e.g. from a sampler
 /// # Returns
-/// * A random Vec3
-pub fn random_vec_range(min: f64, max: f64) -> Vec3 {
-    let mut rng = rand::thread_rng();
-    Vec3::new(
-        rng.gen_range(min..max),
-        rng.gen_range(min..max),
-        rng.gen_range(min..max),
-    )
+/// * A unit vector
+pub fn sample_unit_vector(u: [f64; 2]) -> Vec3 {
+    let z = 1.0 - 2.0 * u[0];
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u[1];
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
-/// Calculate a random vector in a unit sphere
-pub fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = random_vec_range(-1.0, 1.0);
-        if p.length_squared() >= 1.0 {
-            continue;
-        }
-        return p;
-    }
+/// Map three uniform random values to a point in the unit sphere, spread evenly
+/// # Arguments
+/// * 'u' - Values from 0-1 picking the direction
+/// * 'radius' - Value from 0-1 picking the distance from the center
+/// # Returns
+/// * A vector with a length below 1
+pub fn sample_in_unit_sphere(u: [f64; 2], radius: f64) -> Vec3 {
+    // The volume grows with the cube of the radius
+    sample_unit_vector(u) * radius.cbrt()
 }
 
-/// Generate a random unit vector
-pub fn random_unit_vector() -> Vec3 {
-    unit_vector(random_in_unit_sphere())
+/// Map two uniform random values to a direction around a normal, more likely the closer it is to the normal
+/// # Arguments
+/// * 'u' - Values from 0-1, e.g. from a sampler
+/// * 'n' - Unit normal the hemisphere is centered on
+/// # Returns
+/// * A unit vector with a probability proportional to the cosine of its angle to the normal
+pub fn sample_cosine_hemisphere(u: [f64; 2], n: Vec3) -> Vec3 {
+    // Points spread evenly on a disk, projected up onto the hemisphere
+    let r = u[0].sqrt();
+    let phi = 2.0 * PI * u[1];
+    let (x, y) = (r * phi.cos(), r * phi.sin());
+    let z = (1.0 - u[0]).max(0.0).sqrt();
+
+    // Two vectors perpendicular to the normal and each other, from Duff et al. "Building an Orthonormal Basis, Revisited"
+    let sign = 1.0_f64.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    let tangent = Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let bitangent = Vec3::new(b, sign + n.y * n.y * a, -n.y);
+    tangent * x + bitangent * y + n * z
 }
 
 /// Reflect a Vec3 based on a Vec3 and a normal Vec3. Gives a perfect bounce
@@ -1,21 +1,131 @@
-use crate::{Hit, Mesh, Ray};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{Hit, Instance, Mesh, Ray};
 
 /// World struct
 #[derive(Clone, Debug)]
 pub struct World {
-    /// All the meshes in the world
-    pub meshes: Vec<Mesh>,
+    /// All the meshes in the world, private so they stay in step with mesh_ids
+    meshes: Vec<Mesh>,
+    /// All the instances of shared meshes in the world, private so they stay in step with instance_ids
+    instances: Vec<Instance>,
+    /// IDs of each mesh, in the same order as meshes
+    mesh_ids: Vec<ObjectIds>,
+    /// IDs of each instance, in the same order as instances
+    instance_ids: Vec<ObjectIds>,
+    /// Number of object IDs given out so far
+    objects: u32,
+    /// Number of material IDs given out so far
+    materials: u32,
+    /// First material ID of each shared mesh, by its address, so instances of a mesh share its material IDs
+    shared_materials: HashMap<usize, u32>,
+    /// Material ID of each instance's own material, by its address, so instances sharing a material share its ID
+    instance_materials: HashMap<usize, u32>,
+}
+
+/// IDs the world gave an object when it was added
+#[derive(Copy, Clone, Debug)]
+struct ObjectIds {
+    object: u32,
+    /// ID of the object's default material, the material table follows it in order
+    first_material: u32,
+    /// Whether every face uses the first material, for instances with their own material
+    single_material: bool,
 }
 
 impl World {
     /// Create a new empty world
     pub fn new() -> Self {
-        Self { meshes: Vec::new() }
+        Self {
+            meshes: Vec::new(),
+            instances: Vec::new(),
+            mesh_ids: Vec::new(),
+            instance_ids: Vec::new(),
+            objects: 0,
+            materials: 0,
+            shared_materials: HashMap::new(),
+            instance_materials: HashMap::new(),
+        }
     }
 
     /// Add a mesh to the world
-    pub fn add(&mut self, mesh: Mesh) {
+    /// # Returns
+    /// * The mesh's object ID, IDs count up from 0 in the order meshes and instances are added
+    /// # Notes
+    /// * The mesh's default material and each material in its table get their own material ID
+    pub fn add(&mut self, mesh: Mesh) -> u32 {
+        let ids = ObjectIds {
+            object: self.next_object(),
+            first_material: self.next_materials(mesh.materials.len() as u32 + 1),
+            single_material: false,
+        };
+        self.mesh_ids.push(ids);
         self.meshes.push(mesh);
+        ids.object
+    }
+
+    /// Add an instance to the world
+    /// # Returns
+    /// * The instance's object ID, IDs count up from 0 in the order meshes and instances are added
+    /// # Notes
+    /// * Instances of the same shared mesh share its material IDs, and instances sharing their own material share its ID
+    pub fn add_instance(&mut self, instance: Instance) -> u32 {
+        let first_material = match &instance.material {
+            Some(material) => {
+                let key = Arc::as_ptr(material) as usize;
+                match self.instance_materials.get(&key) {
+                    Some(&id) => id,
+                    None => {
+                        let id = self.next_materials(1);
+                        self.instance_materials.insert(key, id);
+                        id
+                    }
+                }
+            }
+            None => {
+                let key = Arc::as_ptr(instance.mesh()) as usize;
+                match self.shared_materials.get(&key) {
+                    Some(&first) => first,
+                    None => {
+                        let first = self.next_materials(instance.mesh().materials.len() as u32 + 1);
+                        self.shared_materials.insert(key, first);
+                        first
+                    }
+                }
+            }
+        };
+        let ids = ObjectIds {
+            object: self.next_object(),
+            first_material,
+            single_material: instance.material.is_some(),
+        };
+        self.instance_ids.push(ids);
+        self.instances.push(instance);
+        ids.object
+    }
+
+    /// All the meshes in the world, in the order they were added
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    /// All the instances of shared meshes in the world, in the order they were added
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    /// Take the next object ID
+    fn next_object(&mut self) -> u32 {
+        self.objects += 1;
+        self.objects - 1
+    }
+
+    /// Take a run of material IDs
+    /// # Returns
+    /// * The first ID of the run
+    fn next_materials(&mut self, count: u32) -> u32 {
+        self.materials += count;
+        self.materials - count
     }
 }
 
@@ -24,15 +134,70 @@ impl World {
     /// # Arguments
     /// * 'r' - The incoming ray
     /// # Returns
-    /// * A hit struct containing the closest hit triangle and its properties
+    /// * A hit struct containing the closest hit triangle and its properties, with the object and material IDs
     pub fn hit(&self, r: Ray) -> Hit {
         let mut closest_hit = Hit::new();
-        for mesh in self.meshes.iter() {
-            let hit = mesh.hit(r);
-            if hit.t > 0.0 && hit.at.z > closest_hit.at.z {
+        let mut closest_ids = None;
+        let hits = self
+            .meshes
+            .iter()
+            .zip(&self.mesh_ids)
+            .map(|(mesh, ids)| (mesh.hit(r), ids, mesh))
+            .chain(
+                self.instances
+                    .iter()
+                    .zip(&self.instance_ids)
+                    .map(|(instance, ids)| (instance.hit(r), ids, &**instance.mesh())),
+            );
+        for (hit, ids, mesh) in hits {
+            if hit.t > 0.0 && (closest_hit.t < 0.0 || hit.t < closest_hit.t) {
+                closest_ids = Some((*ids, mesh.material_slot(&hit.triangle)));
                 closest_hit = hit;
             }
         }
+
+        if let Some((ids, slot)) = closest_ids {
+            closest_hit.object_id = Some(ids.object);
+            closest_hit.material_id = Some(if ids.single_material {
+                ids.first_material
+            } else {
+                ids.first_material + slot as u32
+            });
+        }
         closest_hit
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Diffuse, quad, MaterialEnum, Transform, Vec3};
+
+    #[test]
+    fn instances_sharing_a_material_share_its_id() {
+        let floor = Arc::new(quad(1.0, 1.0));
+        let red = Arc::new(MaterialEnum::Diffuse(Diffuse::new(Vec3::new(
+            1.0, 0.0, 0.0,
+        ))));
+        let also_red = MaterialEnum::Diffuse(Diffuse::new(Vec3::new(1.0, 0.0, 0.0)));
+
+        // Three floors side by side, the first two share one material and the third has a copy of it
+        let mut world = World::new();
+        for (x, material) in [(0.0, red.clone()), (2.0, red), (4.0, Arc::new(also_red))] {
+            world.add_instance(
+                Instance::new(floor.clone(), Transform::translate(Vec3::new(x, 0.0, 0.0)))
+                    .with_material(material),
+            );
+        }
+
+        let material_id = |x| {
+            let hit = world.hit(Ray::new(Vec3::new(x, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)));
+            assert!(hit.t > 0.0);
+            (hit.object_id, hit.material_id.unwrap())
+        };
+        let (first, second, copy) = (material_id(0.0), material_id(2.0), material_id(4.0));
+        assert_ne!(first.0, second.0);
+        assert_eq!(first.1, second.1);
+        assert_ne!(first.1, copy.1);
+    }
+}